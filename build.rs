@@ -0,0 +1,7 @@
+fn main() {
+    println!("cargo:rerun-if-changed=proto/kprf.proto");
+    tonic_build::configure()
+        .build_client(false)
+        .compile(&["proto/kprf.proto"], &["proto"])
+        .unwrap();
+}
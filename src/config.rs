@@ -1,6 +1,9 @@
 use crate::kafka;
 use config::{Config, ConfigError};
 use serde::Deserialize;
+use std::collections::HashMap;
+use std::net::SocketAddr;
+use std::time::Duration;
 
 #[derive(Clone)]
 pub struct AppMetadata {
@@ -38,12 +41,38 @@ pub struct KafkaProxyConfig {
     #[serde(default)]
     http: HttpConfig,
 
+    #[serde(default)]
+    api: ApiConfig,
+
+    #[serde(default)]
+    grpc: GrpcConfig,
+
+    #[serde(default)]
+    auth: AuthConfig,
+
+    #[serde(default)]
+    topics: TopicsConfig,
+
     #[serde(default, skip_deserializing)]
     app_info: AppMetadata,
 
     #[serde(default)]
     kafka: kafka::kafka::config::KafkaConfig,
 
+    // Additional kafka clusters by name. Cluster of `kafka` section is named
+    // 'default'.
+    #[serde(default)]
+    clusters: HashMap<String, kafka::kafka::config::KafkaConfig>,
+
+    #[serde(default)]
+    routes: Vec<RouteConfig>,
+
+    #[serde(default)]
+    mirrors: Vec<MirrorConfig>,
+
+    #[serde(default)]
+    failovers: Vec<FailoverConfig>,
+
     #[serde(default)]
     ratelimit: ratelimit::config::Config,
 }
@@ -61,7 +90,7 @@ impl KafkaProxyConfig {
         return config.unwrap();
     }
 
-    fn initialize_config(config_path: &String) -> Result<Self, ConfigError> {
+    pub(crate) fn initialize_config(config_path: &String) -> Result<Self, ConfigError> {
         let mut cfg = Config::default();
 
         let merge_result = cfg.merge(config::File::with_name(config_path));
@@ -76,6 +105,26 @@ impl KafkaProxyConfig {
         self.http.clone()
     }
 
+    pub fn get_auth_config(&self) -> AuthConfig {
+        self.auth.clone()
+    }
+
+    pub fn get_topics_config(&self) -> TopicsConfig {
+        self.topics.clone()
+    }
+
+    pub fn get_grpc_config(&self) -> GrpcConfig {
+        self.grpc.clone()
+    }
+
+    pub fn get_api_config(&self) -> ApiConfig {
+        let mut api = self.api.clone();
+        if api.delivery_timeout_ms.is_none() {
+            api.delivery_timeout_ms = self.kafka.message_timeout_ms.map(u64::from);
+        }
+        api
+    }
+
     pub fn get_app_info(&self) -> AppMetadata {
         self.app_info.clone()
     }
@@ -88,6 +137,22 @@ impl KafkaProxyConfig {
         self.kafka.clone()
     }
 
+    pub fn get_clusters_config(&self) -> HashMap<String, kafka::kafka::config::KafkaConfig> {
+        self.clusters.clone()
+    }
+
+    pub fn get_routes_config(&self) -> Vec<RouteConfig> {
+        self.routes.clone()
+    }
+
+    pub fn get_mirrors_config(&self) -> Vec<MirrorConfig> {
+        self.mirrors.clone()
+    }
+
+    pub fn get_failovers_config(&self) -> Vec<FailoverConfig> {
+        self.failovers.clone()
+    }
+
     pub fn get_ratelimit_config(&self) -> ratelimit::config::Config {
         self.ratelimit.clone()
     }
@@ -100,6 +165,24 @@ pub struct HttpConfig {
 
     #[serde(default = "HttpConfig::default_metrics_port")]
     metrics_port: Option<u16>,
+
+    // Addresses of API server, e.g. "10.0.0.1:4242" or "[::1]:4242".
+    // Defaults to "0.0.0.0:{port}".
+    #[serde(default)]
+    listen: Option<Vec<String>>,
+
+    // Addresses of metrics server. Defaults to "0.0.0.0:{metrics_port}".
+    #[serde(default)]
+    metrics_listen: Option<Vec<String>>,
+
+    #[serde(default = "HttpConfig::default_tcp_enabled")]
+    tcp_enabled: Option<bool>,
+
+    #[serde(default)]
+    unix_socket: Option<UnixSocketConfig>,
+
+    #[serde(default)]
+    tls: Option<TlsConfig>,
 }
 
 impl Default for HttpConfig {
@@ -107,6 +190,11 @@ impl Default for HttpConfig {
         HttpConfig {
             metrics_port: Some(HttpConfig::DEFAULT_METRICS_PORT),
             port: Some(HttpConfig::DEFAULT_HTTP_PORT),
+            listen: None,
+            metrics_listen: None,
+            tcp_enabled: Some(true),
+            unix_socket: None,
+            tls: None,
         }
     }
 }
@@ -124,6 +212,47 @@ impl HttpConfig {
         self.metrics_port.unwrap()
     }
 
+    /// Addresses API server listens on.
+    pub fn listen_addrs(&self) -> Result<Vec<SocketAddr>, String> {
+        HttpConfig::parse_addrs(&self.listen, self.port())
+    }
+
+    /// Addresses metrics server listens on.
+    pub fn metrics_listen_addrs(&self) -> Result<Vec<SocketAddr>, String> {
+        HttpConfig::parse_addrs(&self.metrics_listen, self.metrics_port())
+    }
+
+    fn parse_addrs(addrs: &Option<Vec<String>>, port: u16) -> Result<Vec<SocketAddr>, String> {
+        let addrs = match addrs {
+            None => return Ok(vec![SocketAddr::from(([0, 0, 0, 0], port))]),
+            Some(addrs) => addrs,
+        };
+        if addrs.is_empty() {
+            return Err(String::from("list of listen addresses is empty"));
+        }
+        addrs
+            .iter()
+            .map(|addr| {
+                addr.parse::<SocketAddr>()
+                    .map_err(|e| format!("invalid listen address '{}': {}", addr, e))
+            })
+            .collect()
+    }
+
+    /// Returns false if API server must listen only on Unix socket.
+    pub fn tcp_enabled(&self) -> bool {
+        self.tcp_enabled.unwrap()
+    }
+
+    pub fn unix_socket(&self) -> Option<UnixSocketConfig> {
+        self.unix_socket.clone()
+    }
+
+    /// TLS settings of TCP listeners. None means plain HTTP.
+    pub fn tls(&self) -> Option<TlsConfig> {
+        self.tls.clone()
+    }
+
     fn default_http_port() -> Option<u16> {
         Some(HttpConfig::DEFAULT_HTTP_PORT)
     }
@@ -131,11 +260,521 @@ impl HttpConfig {
     fn default_metrics_port() -> Option<u16> {
         Some(HttpConfig::DEFAULT_METRICS_PORT)
     }
+
+    fn default_tcp_enabled() -> Option<bool> {
+        Some(true)
+    }
+}
+
+/// UnixSocketConfig describes Unix socket API server listens on.
+#[derive(Clone, Deserialize)]
+pub struct UnixSocketConfig {
+    path: String,
+
+    // File mode of the socket as octal string, e.g. "0660".
+    #[serde(default)]
+    mode: Option<String>,
+
+    // Numeric user id of the socket owner.
+    #[serde(default)]
+    owner: Option<u32>,
+
+    // Numeric group id of the socket.
+    #[serde(default)]
+    group: Option<u32>,
+}
+
+impl UnixSocketConfig {
+    pub fn path(&self) -> &str {
+        &self.path
+    }
+
+    /// Parses socket file mode. None means that mode is left as is.
+    pub fn mode(&self) -> Result<Option<u32>, String> {
+        match &self.mode {
+            None => Ok(None),
+            Some(mode) => u32::from_str_radix(mode, 8)
+                .map(Some)
+                .map_err(|e| format!("invalid unix socket mode '{}': {}", mode, e)),
+        }
+    }
+
+    pub fn owner(&self) -> Option<u32> {
+        self.owner
+    }
+
+    pub fn group(&self) -> Option<u32> {
+        self.group
+    }
+}
+
+/// TlsConfig describes certificates of HTTPS API server.
+#[derive(Clone, Deserialize)]
+pub struct TlsConfig {
+    // Path of PEM-encoded certificate chain of the server.
+    cert_path: String,
+
+    // Path of PEM-encoded private key of the server.
+    key_path: String,
+
+    // Path of PEM-encoded CA certificates used to verify client
+    // certificates. Client certificates are not requested if not set.
+    #[serde(default)]
+    client_ca_path: Option<String>,
+
+    #[serde(default = "TlsConfig::default_client_auth_required")]
+    client_auth_required: Option<bool>,
+}
+
+impl TlsConfig {
+    pub fn cert_path(&self) -> &str {
+        &self.cert_path
+    }
+
+    pub fn key_path(&self) -> &str {
+        &self.key_path
+    }
+
+    pub fn client_ca_path(&self) -> Option<&str> {
+        self.client_ca_path.as_deref()
+    }
+
+    /// Returns false if clients without certificate are allowed to connect.
+    pub fn client_auth_required(&self) -> bool {
+        self.client_auth_required.unwrap()
+    }
+
+    fn default_client_auth_required() -> Option<bool> {
+        Some(true)
+    }
+}
+
+#[derive(Clone, Default, Deserialize)]
+pub struct AuthConfig {
+    #[serde(default)]
+    enabled: bool,
+
+    #[serde(default)]
+    clients: Vec<AuthClientConfig>,
+
+    // Restricts topics clients may produce to.
+    #[serde(default)]
+    acl_enabled: bool,
+
+    #[serde(default)]
+    acls: Vec<AclConfig>,
+}
+
+impl AuthConfig {
+    /// Returns false if requests are accepted without authentication.
+    pub fn enabled(&self) -> bool {
+        self.enabled
+    }
+
+    pub fn clients(&self) -> &[AuthClientConfig] {
+        &self.clients
+    }
+
+    /// Returns true if clients may produce only to topics allowed by ACLs.
+    pub fn acl_enabled(&self) -> bool {
+        self.acl_enabled
+    }
+
+    pub fn acls(&self) -> &[AclConfig] {
+        &self.acls
+    }
+}
+
+/// AclConfig describes topics a client is allowed to produce to. Topic is
+/// allowed if it matches any of exact names, prefixes or glob patterns.
+#[derive(Clone, Deserialize)]
+pub struct AclConfig {
+    client: String,
+
+    #[serde(default)]
+    topics: Vec<String>,
+
+    #[serde(default)]
+    topic_prefixes: Vec<String>,
+
+    // Glob patterns, where '*' matches any sequence of characters and '?'
+    // matches a single character.
+    #[serde(default)]
+    topic_globs: Vec<String>,
+
+    // Clusters the client may select explicitly by `cluster` field.
+    #[serde(default)]
+    clusters: Vec<String>,
+}
+
+impl AclConfig {
+    pub fn client(&self) -> &str {
+        &self.client
+    }
+
+    pub fn topics(&self) -> &[String] {
+        &self.topics
+    }
+
+    pub fn topic_prefixes(&self) -> &[String] {
+        &self.topic_prefixes
+    }
+
+    pub fn topic_globs(&self) -> &[String] {
+        &self.topic_globs
+    }
+
+    pub fn clusters(&self) -> &[String] {
+        &self.clusters
+    }
+}
+
+/// AuthClientConfig describes credentials of a single client. Several
+/// entries with the same name can be used to rotate keys.
+#[derive(Clone, Deserialize)]
+pub struct AuthClientConfig {
+    name: String,
+
+    // Hex-encoded SHA-256 hash of API key of the client.
+    #[serde(default)]
+    key_sha256: Option<String>,
+
+    // Subject of client certificate, if mutual TLS is used.
+    #[serde(default)]
+    cert_subject: Option<String>,
+}
+
+impl AuthClientConfig {
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    pub fn key_sha256(&self) -> Option<&str> {
+        self.key_sha256.as_deref()
+    }
+
+    pub fn cert_subject(&self) -> Option<&str> {
+        self.cert_subject.as_deref()
+    }
+}
+
+/// TopicsConfig describes topics records may be produced to, regardless of
+/// the client. Patterns are regular expressions matched against the whole
+/// topic name.
+#[derive(Clone, Default, Deserialize)]
+pub struct TopicsConfig {
+    #[serde(default)]
+    allow: Vec<String>,
+
+    #[serde(default)]
+    allow_patterns: Vec<String>,
+
+    #[serde(default)]
+    deny: Vec<String>,
+
+    #[serde(default)]
+    deny_patterns: Vec<String>,
+
+    // Clusters records may select explicitly by `cluster` field.
+    #[serde(default)]
+    clusters: Vec<String>,
+}
+
+impl TopicsConfig {
+    /// Returns topics allowed for producing. Any topic is allowed if both
+    /// `allow` and `allow_patterns` are empty.
+    pub fn allow(&self) -> &[String] {
+        &self.allow
+    }
+
+    pub fn allow_patterns(&self) -> &[String] {
+        &self.allow_patterns
+    }
+
+    /// Returns denied topics. Deny rules take precedence over allow ones.
+    pub fn deny(&self) -> &[String] {
+        &self.deny
+    }
+
+    pub fn deny_patterns(&self) -> &[String] {
+        &self.deny_patterns
+    }
+
+    /// Returns clusters records may be produced to by explicit `cluster`
+    /// field, in addition to the cluster chosen by routes.
+    pub fn clusters(&self) -> &[String] {
+        &self.clusters
+    }
+}
+
+/// RouteConfig describes topics which records are produced to the cluster.
+/// Patterns are regular expressions matched against the whole topic name.
+#[derive(Clone, Deserialize)]
+pub struct RouteConfig {
+    cluster: String,
+
+    #[serde(default)]
+    topics: Vec<String>,
+
+    #[serde(default)]
+    topic_patterns: Vec<String>,
+}
+
+impl RouteConfig {
+    pub fn cluster(&self) -> &str {
+        &self.cluster
+    }
+
+    pub fn topics(&self) -> &[String] {
+        &self.topics
+    }
+
+    pub fn topic_patterns(&self) -> &[String] {
+        &self.topic_patterns
+    }
+}
+
+/// MirrorConfig describes a secondary cluster every record of the cluster is
+/// also produced to.
+#[derive(Clone, Deserialize)]
+pub struct MirrorConfig {
+    cluster: String,
+
+    secondary: String,
+
+    // Outcome which decides the response.
+    #[serde(default)]
+    response: kafka::cluster::MirrorResponse,
+}
+
+impl MirrorConfig {
+    pub fn cluster(&self) -> &str {
+        &self.cluster
+    }
+
+    pub fn secondary(&self) -> &str {
+        &self.secondary
+    }
+
+    pub fn response(&self) -> kafka::cluster::MirrorResponse {
+        self.response
+    }
+}
+
+/// FailoverConfig describes a standby cluster records of the cluster are
+/// produced to while all brokers of the cluster are down.
+#[derive(Clone, Deserialize)]
+pub struct FailoverConfig {
+    cluster: String,
+
+    standby: String,
+
+    // How long all brokers of the cluster must be down to switch to standby.
+    #[serde(default = "FailoverConfig::default_failover_after_ms")]
+    failover_after_ms: Option<u64>,
+
+    // How long any broker of the cluster must be up to switch back from
+    // standby.
+    #[serde(default = "FailoverConfig::default_failback_after_ms")]
+    failback_after_ms: Option<u64>,
+}
+
+impl FailoverConfig {
+    const DEFAULT_FAILOVER_AFTER_MS: u64 = 10000;
+    const DEFAULT_FAILBACK_AFTER_MS: u64 = 30000;
+
+    pub fn cluster(&self) -> &str {
+        &self.cluster
+    }
+
+    pub fn standby(&self) -> &str {
+        &self.standby
+    }
+
+    pub fn failover_after(&self) -> Duration {
+        Duration::from_millis(self.failover_after_ms.unwrap())
+    }
+
+    pub fn failback_after(&self) -> Duration {
+        Duration::from_millis(self.failback_after_ms.unwrap())
+    }
+
+    fn default_failover_after_ms() -> Option<u64> {
+        Some(FailoverConfig::DEFAULT_FAILOVER_AFTER_MS)
+    }
+
+    fn default_failback_after_ms() -> Option<u64> {
+        Some(FailoverConfig::DEFAULT_FAILBACK_AFTER_MS)
+    }
+}
+
+#[derive(Clone, Deserialize)]
+pub struct GrpcConfig {
+    #[serde(default)]
+    enabled: bool,
+
+    #[serde(default = "GrpcConfig::default_port")]
+    port: Option<u16>,
+
+    // Addresses of gRPC server, e.g. "10.0.0.1:4243" or "[::1]:4243".
+    // Defaults to "0.0.0.0:{port}".
+    #[serde(default)]
+    listen: Option<Vec<String>>,
+}
+
+impl Default for GrpcConfig {
+    fn default() -> Self {
+        GrpcConfig {
+            enabled: false,
+            port: Some(GrpcConfig::DEFAULT_PORT),
+            listen: None,
+        }
+    }
+}
+
+impl GrpcConfig {
+    const DEFAULT_PORT: u16 = 4243;
+
+    pub fn enabled(&self) -> bool {
+        self.enabled
+    }
+
+    pub fn port(&self) -> u16 {
+        self.port.unwrap()
+    }
+
+    /// Addresses gRPC server listens on.
+    pub fn listen_addrs(&self) -> Result<Vec<SocketAddr>, String> {
+        HttpConfig::parse_addrs(&self.listen, self.port())
+    }
+
+    fn default_port() -> Option<u16> {
+        Some(GrpcConfig::DEFAULT_PORT)
+    }
+}
+
+#[derive(Clone, Deserialize)]
+pub struct ApiConfig {
+    #[serde(default = "ApiConfig::default_max_headers_count")]
+    max_headers_count: Option<usize>,
+
+    #[serde(default = "ApiConfig::default_max_headers_bytes")]
+    max_headers_bytes: Option<usize>,
+
+    #[serde(default = "ApiConfig::default_max_timestamp_skew_ms")]
+    max_timestamp_skew_ms: Option<u64>,
+
+    #[serde(default)]
+    delivery_timeout_ms: Option<u64>,
+
+    #[serde(default = "ApiConfig::default_max_delivery_timeout_ms")]
+    max_delivery_timeout_ms: Option<u64>,
+
+    #[serde(default = "ApiConfig::default_ndjson_max_in_flight")]
+    ndjson_max_in_flight: Option<usize>,
+
+    #[serde(default = "ApiConfig::default_ndjson_max_line_bytes")]
+    ndjson_max_line_bytes: Option<usize>,
+}
+
+impl Default for ApiConfig {
+    fn default() -> Self {
+        ApiConfig {
+            max_headers_count: Some(ApiConfig::DEFAULT_MAX_HEADERS_COUNT),
+            max_headers_bytes: Some(ApiConfig::DEFAULT_MAX_HEADERS_BYTES),
+            max_timestamp_skew_ms: Some(ApiConfig::DEFAULT_MAX_TIMESTAMP_SKEW_MS),
+            delivery_timeout_ms: None,
+            max_delivery_timeout_ms: Some(ApiConfig::DEFAULT_MAX_DELIVERY_TIMEOUT_MS),
+            ndjson_max_in_flight: Some(ApiConfig::DEFAULT_NDJSON_MAX_IN_FLIGHT),
+            ndjson_max_line_bytes: Some(ApiConfig::DEFAULT_NDJSON_MAX_LINE_BYTES),
+        }
+    }
+}
+
+impl ApiConfig {
+    const DEFAULT_MAX_HEADERS_COUNT: usize = 32;
+
+    const DEFAULT_MAX_HEADERS_BYTES: usize = 16 * 1024; // 16 KiB
+
+    const DEFAULT_MAX_TIMESTAMP_SKEW_MS: u64 = 7 * 24 * 60 * 60 * 1000; // 7 days
+
+    const DEFAULT_MAX_DELIVERY_TIMEOUT_MS: u64 = 30000;
+
+    const DEFAULT_NDJSON_MAX_IN_FLIGHT: usize = 1000;
+
+    const DEFAULT_NDJSON_MAX_LINE_BYTES: usize = 1024 * 1024; // 1 MiB
+
+    /// Maximum number of headers allowed in a single record.
+    pub fn max_headers_count(&self) -> usize {
+        self.max_headers_count.unwrap()
+    }
+
+    /// Maximum total size of header keys and values in a single record.
+    pub fn max_headers_bytes(&self) -> usize {
+        self.max_headers_bytes.unwrap()
+    }
+
+    /// Maximum allowed difference between record timestamp and current time.
+    /// Zero disables the check.
+    pub fn max_timestamp_skew_ms(&self) -> u64 {
+        self.max_timestamp_skew_ms.unwrap()
+    }
+
+    /// Returns delivery timeout for a push request. `requested_ms` overrides
+    /// the default one, but can not exceed the configured maximum.
+    pub fn delivery_timeout(&self, requested_ms: Option<u64>) -> Duration {
+        let default_ms = self
+            .delivery_timeout_ms
+            .unwrap_or(ApiConfig::DEFAULT_MAX_DELIVERY_TIMEOUT_MS);
+        let timeout_ms = requested_ms
+            .unwrap_or(default_ms)
+            .min(self.max_delivery_timeout_ms.unwrap());
+        Duration::from_millis(timeout_ms)
+    }
+
+    /// Maximum number of NDJSON records being produced at the same time.
+    pub fn ndjson_max_in_flight(&self) -> usize {
+        self.ndjson_max_in_flight.unwrap().max(1)
+    }
+
+    /// Maximum size of a single line of NDJSON body.
+    pub fn ndjson_max_line_bytes(&self) -> usize {
+        self.ndjson_max_line_bytes.unwrap()
+    }
+
+    fn default_max_headers_count() -> Option<usize> {
+        Some(ApiConfig::DEFAULT_MAX_HEADERS_COUNT)
+    }
+
+    fn default_max_headers_bytes() -> Option<usize> {
+        Some(ApiConfig::DEFAULT_MAX_HEADERS_BYTES)
+    }
+
+    fn default_max_timestamp_skew_ms() -> Option<u64> {
+        Some(ApiConfig::DEFAULT_MAX_TIMESTAMP_SKEW_MS)
+    }
+
+    fn default_max_delivery_timeout_ms() -> Option<u64> {
+        Some(ApiConfig::DEFAULT_MAX_DELIVERY_TIMEOUT_MS)
+    }
+
+    fn default_ndjson_max_in_flight() -> Option<usize> {
+        Some(ApiConfig::DEFAULT_NDJSON_MAX_IN_FLIGHT)
+    }
+
+    fn default_ndjson_max_line_bytes() -> Option<usize> {
+        Some(ApiConfig::DEFAULT_NDJSON_MAX_LINE_BYTES)
+    }
 }
 
 #[cfg(test)]
 mod tests {
     use crate::config::KafkaProxyConfig;
+    use crate::kafka::cluster::MirrorResponse;
+    use crate::kafka::kafka::config::{
+        CompressionCodec, CompressionConfig, SaslMechanism, SecurityProtocol,
+    };
+    use std::time::Duration;
 
     fn prepare_config(config_path: &String) -> KafkaProxyConfig {
         let config = KafkaProxyConfig::initialize_config(config_path);
@@ -186,4 +825,415 @@ mod tests {
         assert_eq!(config.ratelimit.enabled(), true);
         assert_eq!(config.ratelimit.get_rules().len(), 2);
     }
+
+    #[test]
+    fn test_kafkaproxy_config_kafka_security() {
+        let config = prepare_config(&String::from("testdata/kafka_sasl.yaml"));
+        let kafka = config.get_kafka_config();
+        assert_eq!(kafka.security_protocol, Some(SecurityProtocol::SaslSsl));
+        assert_eq!(kafka.sasl_mechanism, Some(SaslMechanism::ScramSha512));
+        assert_eq!(kafka.validate(), Ok(()));
+
+        let hash = kafka.to_hash();
+        assert_eq!(hash["security.protocol"], "sasl_ssl");
+        assert_eq!(hash["sasl.mechanism"], "SCRAM-SHA-512");
+        assert_eq!(hash["ssl.ca.location"], "testdata/tls/ca.pem");
+        assert_eq!(hash["ssl.endpoint.identification.algorithm"], "https");
+
+        let mut invalid = kafka.clone();
+        invalid.password = None;
+        assert!(invalid.validate().is_err());
+
+        let mut invalid = kafka.clone();
+        invalid.sasl_mechanism = None;
+        assert!(invalid.validate().is_err());
+
+        let mut invalid = kafka.clone();
+        invalid.ssl_ca_location = Some(String::from("testdata/tls/missing.pem"));
+        assert!(invalid.validate().is_err());
+
+        let mut invalid = kafka.clone();
+        invalid.ssl_certificate_location = Some(String::from("testdata/tls/client.pem"));
+        assert!(invalid.validate().is_err());
+
+        let mut invalid = kafka.clone();
+        invalid.security_protocol = Some(SecurityProtocol::SaslPlaintext);
+        assert!(invalid.validate().is_err());
+
+        let mut oauth = kafka;
+        oauth.sasl_mechanism = Some(SaslMechanism::OAuthBearer);
+        oauth.user = None;
+        oauth.password = None;
+        assert_eq!(oauth.validate(), Ok(()));
+
+        // plaintext without security settings is valid.
+        let config = prepare_config(&String::from("testdata/kafka_config.yaml"));
+        assert_eq!(config.get_kafka_config().validate(), Ok(()));
+    }
+
+    #[test]
+    fn test_kafkaproxy_config_kafka_legacy_sasl() {
+        let config = prepare_config(&String::from("testdata/kafka_legacy_sasl.yaml"));
+        let kafka = config.get_kafka_config();
+        assert_eq!(kafka.security_protocol, None);
+        assert_eq!(
+            kafka.security_protocol(),
+            Some(SecurityProtocol::SaslPlaintext)
+        );
+        assert_eq!(kafka.sasl_mechanism(), Some(SaslMechanism::Plain));
+        assert_eq!(kafka.validate(), Ok(()));
+
+        let hash = kafka.to_hash();
+        assert_eq!(hash["security.protocol"], "sasl_plaintext");
+        assert_eq!(hash["sasl.mechanism"], "PLAIN");
+        assert_eq!(hash["sasl.username"], "kprf");
+
+        // explicit protocol disables defaults.
+        let mut invalid = kafka;
+        invalid.security_protocol = Some(SecurityProtocol::Plaintext);
+        assert!(invalid.validate().is_err());
+    }
+
+    #[test]
+    fn test_kafkaproxy_config_librdkafka_overrides() {
+        let config = prepare_config(&String::from("testdata/kafka_config.yaml"));
+        let kafka = config.get_kafka_config();
+        assert_eq!(kafka.librdkafka_overrides.len(), 2);
+        assert_eq!(kafka.librdkafka_overrides["client.id"], "kprf");
+        assert_eq!(kafka.validate(), Ok(()));
+        assert_eq!(kafka.client_config().get("client.id"), Some("kprf"));
+        assert!(kafka.client_config().create_native_config().is_ok());
+
+        let mut invalid = kafka.clone();
+        invalid
+            .librdkafka_overrides
+            .insert(String::from("linger.ms"), String::from("100"));
+        assert!(invalid.validate().is_err());
+
+        let mut unknown = kafka.clone();
+        unknown
+            .librdkafka_overrides
+            .insert(String::from("no.such.property"), String::from("1"));
+        assert_eq!(unknown.validate(), Ok(()));
+        assert!(unknown.client_config().create_native_config().is_err());
+
+        let mut invalid_value = kafka;
+        invalid_value.librdkafka_overrides.insert(
+            String::from("socket.keepalive.enable"),
+            String::from("yes please"),
+        );
+        assert!(invalid_value
+            .client_config()
+            .create_native_config()
+            .is_err());
+    }
+
+    #[test]
+    fn test_kafkaproxy_config_kafka_compression() {
+        let config = prepare_config(&String::from("testdata/kafka_config.yaml"));
+        let kafka = config.get_kafka_config();
+        assert_eq!(kafka.compression, Some(CompressionCodec::Lz4));
+        assert_eq!(
+            kafka.topic_compression["logs.nginx"],
+            CompressionConfig {
+                codec: CompressionCodec::Gzip,
+                level: Some(6),
+            }
+        );
+        assert_eq!(kafka.topic_compression["logs.nginx"].name(), "gzip:6");
+        assert_eq!(kafka.validate(), Ok(()));
+        assert_eq!(kafka.to_hash()["compression.codec"], "lz4");
+        assert!(!kafka.to_hash().contains_key("compression.level"));
+
+        let mut invalid = kafka.clone();
+        invalid.compression_level = Some(13);
+        assert!(invalid.validate().is_err());
+
+        let mut invalid = kafka.clone();
+        invalid.topic_compression.insert(
+            String::from("orders"),
+            CompressionConfig {
+                codec: CompressionCodec::Snappy,
+                level: Some(1),
+            },
+        );
+        assert!(invalid.validate().is_err());
+
+        let config = prepare_config(&String::from("testdata/ratelimit.yaml"));
+        assert!(config.get_kafka_config().default_compression().is_none());
+    }
+
+    #[test]
+    fn test_kafkaproxy_config_kafka_idempotent() {
+        let config = prepare_config(&String::from("testdata/kafka_config.yaml"));
+        let mut kafka = config.get_kafka_config();
+        assert_eq!(kafka.idempotent, false); // default value
+        assert!(!kafka.to_hash().contains_key("enable.idempotence"));
+
+        kafka.idempotent = true;
+        // explicit acks must be 'all'.
+        assert!(kafka.validate().is_err());
+        kafka.request_required_acks = Some(-1);
+        assert_eq!(kafka.validate(), Ok(()));
+        kafka.request_required_acks = None;
+        assert_eq!(kafka.validate(), Ok(()));
+        let hash = kafka.to_hash();
+        assert_eq!(hash["enable.idempotence"], "true");
+        assert_eq!(hash["statistics.interval.ms"], "1000");
+        // librdkafka adjusts acks and in-flight limit which are not set.
+        assert!(!hash.contains_key("request.required.acks"));
+        assert!(!hash.contains_key("max.in.flight.requests.per.connection"));
+
+        let mut valid = kafka.clone();
+        valid.librdkafka_overrides.insert(
+            String::from("max.in.flight.requests.per.connection"),
+            String::from("3"),
+        );
+        assert_eq!(valid.validate(), Ok(()));
+        assert_eq!(
+            valid
+                .client_config()
+                .get("max.in.flight.requests.per.connection"),
+            Some("3")
+        );
+
+        let mut invalid = kafka.clone();
+        invalid.retries = Some(0);
+        assert!(invalid.validate().is_err());
+
+        let mut invalid = kafka.clone();
+        invalid.librdkafka_overrides.insert(
+            String::from("max.in.flight.requests.per.connection"),
+            String::from("10"),
+        );
+        assert!(invalid.validate().is_err());
+
+        let mut invalid = kafka;
+        invalid
+            .librdkafka_overrides
+            .insert(String::from("enable.idempotence"), String::from("false"));
+        assert!(invalid.validate().is_err());
+    }
+
+    #[test]
+    fn test_kafkaproxy_config_kafka_transactions() {
+        let config = prepare_config(&String::from("testdata/kafka_config.yaml"));
+        let kafka = config.get_kafka_config();
+        let transactions = &kafka.transactions;
+        assert!(transactions.enabled);
+        assert_eq!(transactions.pool_size(), 2);
+        assert_eq!(transactions.id_prefix(), "kprf-test");
+        assert_eq!(transactions.timeout_ms(), 10000); // default value
+        assert_eq!(kafka.validate(), Ok(()));
+        assert_eq!(kafka.transactional_id(1), "kprf-test-1");
+        // default producer is not transactional.
+        assert!(!kafka.to_hash().contains_key("transactional.id"));
+        assert!(!kafka.to_hash().contains_key("enable.idempotence"));
+
+        let mut invalid = kafka.clone();
+        invalid.transactions.pool_size = Some(0);
+        assert!(invalid.validate().is_err());
+
+        let mut invalid = kafka.clone();
+        invalid.transactions.timeout_ms = Some(1000);
+        assert!(invalid.validate().is_err());
+
+        let mut invalid = kafka.clone();
+        invalid.retries = Some(0);
+        assert!(invalid.validate().is_err());
+
+        let mut invalid = kafka;
+        invalid
+            .librdkafka_overrides
+            .insert(String::from("transactional.id"), String::from("kprf"));
+        assert!(invalid.validate().is_err());
+
+        let kafka = prepare_config(&String::from("testdata/kafka_sasl.yaml")).get_kafka_config();
+        assert!(!kafka.transactions.enabled); // default value
+    }
+
+    #[test]
+    fn test_kafkaproxy_config_clusters() {
+        let config = prepare_config(&String::from("testdata/kafka_config.yaml"));
+        let clusters = config.get_clusters_config();
+        assert_eq!(clusters.len(), 2);
+        let billing = &clusters["billing"];
+        assert_eq!(billing.brokers, vec![String::from("127.0.0.1:9093")]);
+        assert!(billing.idempotent);
+        assert_eq!(billing.message_timeout_ms, Some(2000)); // default value
+        assert_eq!(billing.validate(), Ok(()));
+
+        let routes = config.get_routes_config();
+        assert_eq!(routes.len(), 1);
+        assert_eq!(routes[0].cluster(), "billing");
+        assert_eq!(routes[0].topics(), &[String::from("payments")]);
+        assert_eq!(routes[0].topic_patterns(), &[String::from("billing\\..+")]);
+
+        let mirrors = config.get_mirrors_config();
+        assert_eq!(mirrors.len(), 1);
+        assert_eq!(mirrors[0].cluster(), "billing");
+        assert_eq!(mirrors[0].secondary(), "default");
+        assert_eq!(mirrors[0].response(), MirrorResponse::Both);
+
+        let failovers = config.get_failovers_config();
+        assert_eq!(failovers.len(), 1);
+        assert_eq!(failovers[0].cluster(), "default");
+        assert_eq!(failovers[0].standby(), "standby");
+        assert_eq!(failovers[0].failover_after(), Duration::from_millis(5000));
+        assert_eq!(failovers[0].failback_after(), Duration::from_millis(30000)); // default value
+
+        let config = prepare_config(&String::from("testdata/kafka_sasl.yaml"));
+        assert!(config.get_clusters_config().is_empty()); // default value
+        assert!(config.get_routes_config().is_empty()); // default value
+        assert!(config.get_mirrors_config().is_empty()); // default value
+        assert!(config.get_failovers_config().is_empty()); // default value
+    }
+
+    #[test]
+    fn test_kafkaproxy_config_listen_addrs() {
+        let config = prepare_config(&String::from("testdata/kafka_config.yaml"));
+        let addrs = config.http.listen_addrs().unwrap();
+        assert_eq!(addrs.len(), 2);
+        assert_eq!(addrs[0].to_string(), "127.0.0.1:4242");
+        assert_eq!(addrs[1].to_string(), "[::1]:4242");
+        // metrics_port is used if listen addresses are not set.
+        assert_eq!(
+            config.http.metrics_listen_addrs().unwrap()[0].to_string(),
+            "0.0.0.0:8089"
+        );
+
+        let mut http = config.http.clone();
+        http.metrics_listen = Some(vec![String::from("localhost")]);
+        assert!(http.metrics_listen_addrs().is_err());
+        http.metrics_listen = Some(vec![]);
+        assert!(http.metrics_listen_addrs().is_err());
+    }
+
+    #[test]
+    fn test_kafkaproxy_config_unix_socket() {
+        let config = prepare_config(&String::from("testdata/kafka_config.yaml"));
+        assert_eq!(config.http.tcp_enabled(), true); // default value
+
+        let socket = config.http.unix_socket().unwrap();
+        assert_eq!(socket.path(), "/tmp/kprf.sock");
+        assert_eq!(socket.mode().unwrap(), Some(0o660));
+        assert_eq!(socket.owner(), None);
+        assert_eq!(socket.group(), Some(33));
+
+        let config = prepare_config(&String::from("testdata/ratelimit.yaml"));
+        assert!(config.http.unix_socket().is_none());
+    }
+
+    #[test]
+    fn test_kafkaproxy_config_tls() {
+        let config = prepare_config(&String::from("testdata/kafka_config.yaml"));
+        let tls = config.http.tls().unwrap();
+        assert_eq!(tls.cert_path(), "testdata/tls/server.pem");
+        assert_eq!(tls.key_path(), "testdata/tls/server.key");
+        assert_eq!(tls.client_ca_path(), Some("testdata/tls/ca.pem"));
+        assert_eq!(tls.client_auth_required(), true); // default value
+
+        let config = prepare_config(&String::from("testdata/ratelimit.yaml"));
+        assert!(config.http.tls().is_none());
+    }
+
+    #[test]
+    fn test_kafkaproxy_config_auth() {
+        let config = prepare_config(&String::from("testdata/kafka_config.yaml"));
+        let auth = config.get_auth_config();
+        assert_eq!(auth.enabled(), true);
+        assert_eq!(auth.clients().len(), 2);
+        assert_eq!(auth.clients()[0].name(), "php-backend");
+        assert!(auth.clients()[0].key_sha256().is_some());
+        assert_eq!(
+            auth.clients()[1].cert_subject(),
+            Some("O=city-mobil, CN=php-backend")
+        );
+        assert_eq!(auth.acl_enabled(), true);
+        assert_eq!(auth.acls().len(), 1);
+        assert_eq!(auth.acls()[0].client(), "php-backend");
+        assert_eq!(auth.acls()[0].topics(), ["orders"]);
+        assert_eq!(auth.acls()[0].topic_prefixes(), ["logs."]);
+        assert_eq!(auth.acls()[0].topic_globs(), ["events-*-v?"]);
+
+        let config = prepare_config(&String::from("testdata/ratelimit.yaml"));
+        assert_eq!(config.get_auth_config().enabled(), false);
+        assert_eq!(config.get_auth_config().acl_enabled(), false);
+    }
+
+    #[test]
+    fn test_kafkaproxy_config_topics() {
+        let config = prepare_config(&String::from("testdata/kafka_config.yaml"));
+        let topics = config.get_topics_config();
+        assert_eq!(topics.allow(), ["orders"]);
+        assert_eq!(topics.allow_patterns(), ["logs\\..+"]);
+        assert_eq!(topics.deny(), ["logs.debug"]);
+        assert_eq!(topics.deny_patterns(), ["__.*"]);
+
+        let config = prepare_config(&String::from("testdata/ratelimit.yaml"));
+        assert!(config.get_topics_config().allow().is_empty());
+        assert!(config.get_topics_config().deny_patterns().is_empty());
+    }
+
+    #[test]
+    fn test_kafkaproxy_config_kafka_auto_create_topics() {
+        let config = prepare_config(&String::from("testdata/kafka_config.yaml"));
+        let kafka = config.get_kafka_config();
+        assert_eq!(kafka.allow_auto_create_topics, Some(false));
+        assert_eq!(kafka.to_hash()["allow.auto.create.topics"], "false");
+
+        let config = prepare_config(&String::from("testdata/ratelimit.yaml"));
+        let kafka = config.get_kafka_config();
+        assert_eq!(kafka.allow_auto_create_topics, None); // default value
+        assert!(!kafka.to_hash().contains_key("allow.auto.create.topics"));
+    }
+
+    #[test]
+    fn test_kafkaproxy_config_grpc() {
+        let config = prepare_config(&String::from("testdata/kafka_config.yaml"));
+        assert_eq!(config.grpc.enabled(), true);
+        assert_eq!(config.grpc.port(), 4243); // default value
+        let addrs = config.grpc.listen_addrs().unwrap();
+        assert_eq!(addrs.len(), 2);
+        assert_eq!(addrs[0].to_string(), "127.0.0.1:4243");
+        assert_eq!(addrs[1].to_string(), "[::1]:4243");
+
+        let config = prepare_config(&String::from("testdata/ratelimit.yaml"));
+        assert_eq!(config.grpc.enabled(), false);
+        let addrs = config.grpc.listen_addrs().unwrap();
+        assert_eq!(addrs[0].to_string(), "0.0.0.0:4243"); // default value
+    }
+
+    #[test]
+    fn test_kafkaproxy_config_api() {
+        let config_path = String::from("testdata/kafka_config.yaml");
+        let config = prepare_config(&config_path);
+
+        assert_eq!(config.api.max_headers_count(), 16);
+        assert_eq!(config.api.max_headers_bytes(), 16384); // default value
+        assert_eq!(config.api.max_timestamp_skew_ms(), 3600000);
+        assert_eq!(config.api.ndjson_max_in_flight(), 100);
+        assert_eq!(config.api.ndjson_max_line_bytes(), 1048576); // default value
+    }
+
+    #[test]
+    fn test_kafkaproxy_config_delivery_timeout() {
+        let config_path = String::from("testdata/kafka_config.yaml");
+        let config = prepare_config(&config_path);
+        let api = config.get_api_config();
+
+        // kafka.message_timeout_ms default value.
+        assert_eq!(api.delivery_timeout(None), Duration::from_millis(2000));
+        assert_eq!(api.delivery_timeout(Some(50)), Duration::from_millis(50));
+        assert_eq!(
+            api.delivery_timeout(Some(60000)),
+            Duration::from_millis(10000)
+        );
+
+        let config = prepare_config(&String::from("testdata/ratelimit.yaml"));
+        assert_eq!(
+            config.get_api_config().delivery_timeout(None),
+            Duration::from_millis(500)
+        );
+    }
 }
@@ -0,0 +1,242 @@
+use crate::config::TlsConfig;
+use crate::http::api_handler::api::{requests, ApiHandler};
+use crate::http::api_handler::proto;
+use crate::http::api_handler::proto::kafka_proxy_server::{KafkaProxy, KafkaProxyServer};
+use crate::http::auth::{self, AuthError, Authenticator, ClientIdentity};
+use crate::http::tls::{self, ClientCert};
+use crate::log::kflog;
+use futures::{FutureExt, StreamExt};
+use std::convert::TryFrom;
+use std::net::SocketAddr;
+use std::sync::Arc;
+use std::time::SystemTime;
+use tokio::sync::oneshot;
+use tokio::sync::oneshot::Receiver;
+use tokio_stream::wrappers::TcpListenerStream;
+use tonic::{Request, Response, Status, Streaming};
+
+lazy_static::lazy_static! {
+    static ref REQUEST_DURATION: prometheus::HistogramVec = prometheus::register_histogram_vec!(
+        "grpc_requests_duration",
+        "Duration of gRPC requests",
+        &["code", "method"],
+        prometheus::exponential_buckets(5.0, 2.0, 5).unwrap()
+    ).unwrap();
+}
+
+pub struct Config {
+    addrs: Vec<SocketAddr>,
+    // TLS settings of listeners, the same as of HTTP server.
+    tls: Option<TlsConfig>,
+}
+
+impl Config {
+    pub fn new(addrs: Vec<SocketAddr>, tls: Option<TlsConfig>) -> Config {
+        return Config { addrs, tls };
+    }
+}
+
+pub struct Server {
+    config: Config,
+}
+
+struct KafkaProxyService {
+    logger: kflog::Logger,
+    api_handler: Arc<ApiHandler>,
+    authenticator: Arc<Authenticator>,
+}
+
+impl KafkaProxyService {
+    /// Authenticates client by API key passed in `authorization` or
+    /// `x-api-key` metadata, or by TLS client certificate.
+    fn authenticate<T>(&self, request: &Request<T>) -> Result<Option<ClientIdentity>, AuthError> {
+        let metadata = request.metadata();
+        let authorization = metadata.get("authorization").and_then(|v| v.to_str().ok());
+        let x_api_key = metadata.get("x-api-key").and_then(|v| v.to_str().ok());
+        let cert = request
+            .extensions()
+            .get::<Option<ClientCert>>()
+            .and_then(|cert| cert.as_ref());
+        self.authenticator
+            .authenticate(auth::api_key(authorization, x_api_key), cert)
+    }
+
+    async fn handle_push(
+        &self,
+        req: proto::PushRequest,
+        client: Option<ClientIdentity>,
+    ) -> Result<requests::PushResponse, Status> {
+        let req = requests::PushRequest::try_from(req).map_err(|e| {
+            slog::warn!(
+                self.logger,
+                "got invalid grpc push request";
+                "error" => &e,
+            );
+            Status::invalid_argument(e)
+        })?;
+        Ok(self.api_handler.handle_push(req, client).await)
+    }
+
+    fn observe(
+        &self,
+        method: &str,
+        start: SystemTime,
+        result: &Result<requests::PushResponse, Status>,
+    ) {
+        let passed = SystemTime::now()
+            .duration_since(start)
+            .map(|psd| (psd.as_micros() as f64) / 1000.0)
+            .unwrap_or_default();
+
+        // NOTE: code label uses HTTP status codes, the same as for HTTP API.
+        let code = match result {
+            Ok(response) => response.status_code(),
+            Err(status) if status.code() == tonic::Code::Unauthenticated => 401,
+            Err(_) => 400,
+        };
+        REQUEST_DURATION
+            .with_label_values(&[&code.to_string(), method])
+            .observe(passed);
+
+        slog::debug!(
+            self.logger,
+            "proceeded_grpc_request";
+            "method" => method,
+            "passed" => (passed).to_string() + "ms",
+        );
+    }
+}
+
+#[tonic::async_trait]
+impl KafkaProxy for KafkaProxyService {
+    async fn push(
+        &self,
+        request: Request<proto::PushRequest>,
+    ) -> Result<Response<proto::PushResponse>, Status> {
+        let start = SystemTime::now();
+        let result = match self.authenticate(&request) {
+            Ok(client) => self.handle_push(request.into_inner(), client).await,
+            Err(e) => Err(Status::unauthenticated(e.message())),
+        };
+        self.observe("push", start, &result);
+
+        result.map(|response| Response::new(proto::PushResponse::from(&response)))
+    }
+
+    async fn push_stream(
+        &self,
+        request: Request<Streaming<proto::PushRequest>>,
+    ) -> Result<Response<proto::PushResponse>, Status> {
+        let start = SystemTime::now();
+        let client = match self.authenticate(&request) {
+            Ok(client) => client,
+            Err(e) => {
+                let result = Err(Status::unauthenticated(e.message()));
+                self.observe("push_stream", start, &result);
+                return result.map(|response| Response::new(proto::PushResponse::from(&response)));
+            }
+        };
+        let mut stream = request.into_inner();
+
+        let mut result = Ok(requests::PushResponse::ok());
+        while let Some(req) = stream.message().await? {
+            let response = match self.handle_push(req, client.clone()).await {
+                Ok(response) => response,
+                Err(e) => {
+                    result = Err(e);
+                    break;
+                }
+            };
+            if let Ok(result) = result.as_mut() {
+                result.append(response);
+            }
+        }
+        self.observe("push_stream", start, &result);
+
+        result.map(|response| Response::new(proto::PushResponse::from(&response)))
+    }
+}
+
+impl Server {
+    pub fn new_from_config(config: Config) -> Server {
+        return Server { config };
+    }
+
+    pub fn start_server(
+        &mut self,
+        logger: kflog::Logger,
+        api_handler: Arc<ApiHandler>,
+        authenticator: Arc<Authenticator>,
+        shutdown_rx: Receiver<String>,
+    ) -> Receiver<i8> {
+        let logger_cloned = logger.clone();
+        let service = KafkaProxyService {
+            logger: logger.clone(),
+            api_handler,
+            authenticator,
+        };
+
+        let (shutdown_completed_tx, shutdown_completed_rx) = oneshot::channel::<i8>();
+
+        // NOTE: the same shutdown signal is shared between all listeners.
+        let shutdown = async move {
+            shutdown_rx.await.ok();
+            slog::info!(logger_cloned, "shutting down grpc server");
+        }
+        .shared();
+
+        let service = KafkaProxyServer::new(service);
+        let tls_acceptor = self.config.tls.as_ref().map(|tls| {
+            tls::new_acceptor(tls)
+                .unwrap_or_else(|e| panic!("failed to initialize tls of grpc server: {}", e))
+        });
+        let mut servers = vec![];
+        for addr in &self.config.addrs {
+            slog::info!(
+                logger,
+                "starting grpc server";
+                "addr" => addr.to_string(),
+                "tls" => tls_acceptor.is_some(),
+            );
+
+            let listener = std::net::TcpListener::bind(addr)
+                .and_then(|l| l.set_nonblocking(true).map(|_| l))
+                .and_then(tokio::net::TcpListener::from_std)
+                .unwrap_or_else(|e| panic!("failed to bind grpc server to '{}': {}", addr, e));
+            let router = tonic::transport::Server::builder().add_service(service.clone());
+            let server = match &tls_acceptor {
+                Some(acceptor) => {
+                    let incoming = tls::incoming(listener, acceptor.clone(), logger.clone())
+                        .map(|conn| conn.map(tls::TlsConnection));
+                    router
+                        .serve_with_incoming_shutdown(incoming, shutdown.clone())
+                        .boxed()
+                }
+                None => router
+                    .serve_with_incoming_shutdown(
+                        TcpListenerStream::new(listener),
+                        shutdown.clone(),
+                    )
+                    .boxed(),
+            };
+            let logger = logger.clone();
+            servers.push(tokio::task::spawn(async move {
+                if let Err(e) = server.await {
+                    slog::error!(
+                        logger,
+                        "grpc server failed";
+                        "error" => e.to_string(),
+                    );
+                }
+            }));
+        }
+
+        tokio::task::spawn(async move {
+            for server in servers {
+                server.await.ok();
+            }
+            shutdown_completed_tx.send(0).ok();
+        });
+        return shutdown_completed_rx;
+    }
+}
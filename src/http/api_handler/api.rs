@@ -1,36 +1,422 @@
-use crate::http::api_handler::api::requests::PushResponseError;
+use crate::config::ApiConfig;
+use crate::http::api_handler::api::requests::{ErrorCode, PushResponseError};
+use crate::http::api_handler::ndjson;
+use crate::http::api_handler::topics::{TopicPolicy, TopicRoutes};
+use crate::http::auth::{Authorizer, ClientIdentity, TopicAcl};
+use crate::kafka::cluster::{Cluster, Clusters};
 use crate::kafka::kafka::producer;
 use crate::log::kflog;
+use bytes::Buf;
+use futures::stream::{FuturesUnordered, Stream, StreamExt};
 use rdkafka::error::KafkaError;
-use rdkafka::message::OwnedMessage;
-use rdkafka::producer::future_producer::OwnedDeliveryResult;
-use rdkafka::Timestamp;
+use std::borrow::Cow;
+use std::collections::VecDeque;
 use std::sync::Arc;
-use std::time::Duration;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
 //use uuid::Uuid;
 
 pub(crate) mod requests {
+    use crate::kafka::kafka::producer;
+    use bytes::Bytes;
+    use rdkafka::error::{KafkaError, RDKafkaErrorCode};
+    use rdkafka::message::OwnedHeaders;
     use serde::Deserialize;
     use serde::Serialize;
+    use std::borrow::Cow;
+    use std::collections::BTreeMap;
+
+    /// Encoding describes how `data` and `key` of a record are represented
+    /// in request. Binary payloads (Avro, Protobuf, compressed blobs) of JSON
+    /// requests must be sent as `base64` or `hex`, binary request formats can
+    /// pass them as raw bytes with `utf8` encoding.
+    #[derive(Debug, Clone, Copy, PartialEq, Deserialize)]
+    #[serde(rename_all = "lowercase")]
+    pub enum Encoding {
+        Utf8,
+        Base64,
+        Hex,
+    }
+
+    impl Default for Encoding {
+        fn default() -> Self {
+            Encoding::Utf8
+        }
+    }
+
+    impl Encoding {
+        pub fn decode<'a>(&self, value: &'a [u8]) -> Result<Cow<'a, [u8]>, String> {
+            match self {
+                Encoding::Utf8 => Ok(Cow::Borrowed(value)),
+                Encoding::Base64 => base64::decode(value)
+                    .map(Cow::Owned)
+                    .map_err(|e| e.to_string()),
+                Encoding::Hex => hex::decode(value)
+                    .map(Cow::Owned)
+                    .map_err(|e| e.to_string()),
+            }
+        }
+    }
+
+    /// Decoded record data and key.
+    pub type Decoded<'a> = (Option<Cow<'a, [u8]>>, Option<Cow<'a, [u8]>>);
+
+    /// Deserializes data or key of a record either from a string or, in
+    /// binary request formats, from raw bytes.
+    fn deserialize_bytes<'de, D>(deserializer: D) -> Result<Option<Vec<u8>>, D::Error>
+    where
+        D: serde::Deserializer<'de>,
+    {
+        struct BytesVisitor;
+
+        impl<'de> serde::de::Visitor<'de> for BytesVisitor {
+            type Value = Option<Vec<u8>>;
+
+            fn expecting(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+                f.write_str("string, bytes or null")
+            }
+
+            fn visit_none<E: serde::de::Error>(self) -> Result<Self::Value, E> {
+                Ok(None)
+            }
+
+            fn visit_unit<E: serde::de::Error>(self) -> Result<Self::Value, E> {
+                Ok(None)
+            }
+
+            fn visit_some<D>(self, deserializer: D) -> Result<Self::Value, D::Error>
+            where
+                D: serde::Deserializer<'de>,
+            {
+                deserializer.deserialize_any(self)
+            }
+
+            fn visit_str<E: serde::de::Error>(self, v: &str) -> Result<Self::Value, E> {
+                Ok(Some(v.as_bytes().to_vec()))
+            }
+
+            fn visit_string<E: serde::de::Error>(self, v: String) -> Result<Self::Value, E> {
+                Ok(Some(v.into_bytes()))
+            }
+
+            fn visit_bytes<E: serde::de::Error>(self, v: &[u8]) -> Result<Self::Value, E> {
+                Ok(Some(v.to_vec()))
+            }
+
+            fn visit_byte_buf<E: serde::de::Error>(self, v: Vec<u8>) -> Result<Self::Value, E> {
+                Ok(Some(v))
+            }
+        }
+
+        deserializer.deserialize_option(BytesVisitor)
+    }
+
+    #[derive(Debug, Deserialize)]
+    pub struct Header {
+        pub key: String,
+        pub value: String,
+        #[serde(default)]
+        pub encoding: Encoding,
+    }
+
+    /// Headers can be passed either as a list of `{"key", "value", "encoding"}`
+    /// objects or as a plain map of string values.
+    #[derive(Debug, Deserialize)]
+    #[serde(untagged)]
+    pub enum Headers {
+        List(Vec<Header>),
+        Map(BTreeMap<String, String>),
+    }
+
+    /// Timestamp of a record: either milliseconds since epoch or RFC3339
+    /// string.
+    #[derive(Debug, Deserialize)]
+    #[serde(untagged)]
+    pub enum Timestamp {
+        Millis(i64),
+        Rfc3339(String),
+    }
+
+    impl Timestamp {
+        pub fn as_millis(&self) -> Result<i64, String> {
+            match self {
+                Timestamp::Millis(ms) => Ok(*ms),
+                Timestamp::Rfc3339(s) => chrono::DateTime::parse_from_rfc3339(s)
+                    .map(|dt| dt.timestamp_millis())
+                    .map_err(|e| format!("failed to parse timestamp '{}': {}", s, e)),
+            }
+        }
+    }
 
     #[derive(Debug, Deserialize)]
     pub struct Record {
-        pub data: String,
+        /// Null data produces a tombstone. The field is still required, so
+        /// that a record without data is not deleted by mistake.
+        #[serde(deserialize_with = "deserialize_bytes")]
+        pub data: Option<Vec<u8>>,
         pub topic: String,
-        pub key: Option<String>,
+        #[serde(default, deserialize_with = "deserialize_bytes")]
+        pub key: Option<Vec<u8>>,
         pub partition: Option<i32>,
+        #[serde(default)]
+        pub data_encoding: Encoding,
+        #[serde(default)]
+        pub key_encoding: Encoding,
+        pub headers: Option<Headers>,
+        pub timestamp: Option<Timestamp>,
+        /// Name of kafka cluster the record is produced to. If not set, the
+        /// cluster is chosen by topic routes.
+        pub cluster: Option<String>,
+    }
+
+    impl Record {
+        /// Decodes record data and key according to their encodings.
+        pub fn decode(&self) -> Result<Decoded<'_>, String> {
+            let data = match &self.data {
+                None => None,
+                Some(d) => Some(
+                    self.data_encoding
+                        .decode(d)
+                        .map_err(|e| format!("failed to decode data: {}", e))?,
+                ),
+            };
+            let key = match &self.key {
+                None => None,
+                Some(k) => Some(
+                    self.key_encoding
+                        .decode(k)
+                        .map_err(|e| format!("failed to decode key: {}", e))?,
+                ),
+            };
+            Ok((data, key))
+        }
+
+        /// Decodes record headers and checks them against the given limits.
+        pub fn decode_headers(
+            &self,
+            max_count: usize,
+            max_bytes: usize,
+        ) -> Result<Option<OwnedHeaders>, String> {
+            let decoded = match &self.headers {
+                None => return Ok(None),
+                Some(Headers::List(list)) => {
+                    let mut decoded = Vec::with_capacity(list.len());
+                    for h in list.iter() {
+                        let value = h
+                            .encoding
+                            .decode(h.value.as_bytes())
+                            .map_err(|e| format!("failed to decode header '{}': {}", h.key, e))?;
+                        decoded.push((h.key.as_str(), value));
+                    }
+                    decoded
+                }
+                Some(Headers::Map(map)) => map
+                    .iter()
+                    .map(|(k, v)| (k.as_str(), Cow::Borrowed(v.as_bytes())))
+                    .collect(),
+            };
+
+            build_headers(&decoded, max_count, max_bytes).map(Some)
+        }
+
+        /// Parses record timestamp and checks that it differs from `now_ms` by
+        /// no more than `max_skew_ms`. Zero `max_skew_ms` disables the check.
+        pub fn decode_timestamp(
+            &self,
+            now_ms: i64,
+            max_skew_ms: u64,
+        ) -> Result<Option<i64>, String> {
+            let ts = match &self.timestamp {
+                None => return Ok(None),
+                Some(t) => t.as_millis()?,
+            };
+            if ts < 0 {
+                return Err(format!("timestamp must not be negative: {}", ts));
+            }
+            if max_skew_ms != 0 && (now_ms - ts).unsigned_abs() > max_skew_ms {
+                return Err(format!(
+                    "timestamp {} differs from current time by more than {}ms",
+                    ts, max_skew_ms
+                ));
+            }
+            Ok(Some(ts))
+        }
+    }
+
+    /// Checks headers against the given limits and converts them into kafka
+    /// headers.
+    pub fn build_headers(
+        headers: &[(&str, Cow<[u8]>)],
+        max_count: usize,
+        max_bytes: usize,
+    ) -> Result<OwnedHeaders, String> {
+        if headers.len() > max_count {
+            return Err(format!(
+                "too many headers: {} (max {})",
+                headers.len(),
+                max_count
+            ));
+        }
+        let size: usize = headers.iter().map(|(k, v)| k.len() + v.len()).sum();
+        if size > max_bytes {
+            return Err(format!(
+                "headers are too large: {} bytes (max {})",
+                size, max_bytes
+            ));
+        }
+
+        Ok(headers.iter().fold(
+            OwnedHeaders::new_with_capacity(headers.len()),
+            |h, (k, v)| h.add(k, v.as_ref()),
+        ))
+    }
+
+    /// RawRecord is a single record which payload is passed as request body.
+    #[derive(Debug)]
+    pub struct RawRecord {
+        pub topic: String,
+        pub partition: Option<i32>,
+        pub payload: Bytes,
+        pub key: Option<Vec<u8>>,
+        pub headers: Vec<(String, Vec<u8>)>,
+        pub wait_for_send: bool,
+        pub timeout_ms: Option<u64>,
     }
 
     #[derive(Debug, Deserialize)]
     pub struct PushRequest {
         pub records: Vec<Record>,
         pub wait_for_send: Option<bool>,
+        /// Delivery timeout of the request records in milliseconds.
+        pub timeout_ms: Option<u64>,
+        /// Produce records in a single transaction: either all of them are
+        /// delivered, or none.
+        pub atomic: Option<bool>,
     }
 
+    /// ErrorCode is a stable machine-readable reason of record failure.
+    #[derive(Debug, Clone, Copy, PartialEq, Serialize)]
+    #[serde(rename_all = "SCREAMING_SNAKE_CASE")]
+    pub enum ErrorCode {
+        RateLimited,
+        QueueFull,
+        MessageTooLarge,
+        UnknownTopic,
+        Timeout,
+        Unavailable,
+        InvalidRequest,
+        Unauthorized,
+        Forbidden,
+        /// Record was valid, but it was rolled back together with other
+        /// records of atomic push.
+        TransactionAborted,
+        /// Record of atomic push was committed to the primary cluster, but
+        /// transaction of its mirror failed.
+        MirrorFailed,
+        Internal,
+    }
+
+    impl ErrorCode {
+        pub fn from_kafka_error(err: &KafkaError) -> ErrorCode {
+            let code = match err.rdkafka_error_code() {
+                None => return ErrorCode::Internal,
+                Some(code) => code,
+            };
+            match code {
+                RDKafkaErrorCode::QueueFull => ErrorCode::QueueFull,
+                RDKafkaErrorCode::MessageSizeTooLarge | RDKafkaErrorCode::InvalidMessageSize => {
+                    ErrorCode::MessageTooLarge
+                }
+                RDKafkaErrorCode::UnknownTopic
+                | RDKafkaErrorCode::UnknownPartition
+                | RDKafkaErrorCode::UnknownTopicOrPartition => ErrorCode::UnknownTopic,
+                RDKafkaErrorCode::MessageTimedOut
+                | RDKafkaErrorCode::RequestTimedOut
+                | RDKafkaErrorCode::OperationTimedOut => ErrorCode::Timeout,
+                RDKafkaErrorCode::AllBrokersDown
+                | RDKafkaErrorCode::BrokerNotAvailable
+                | RDKafkaErrorCode::LeaderNotAvailable
+                | RDKafkaErrorCode::NotLeaderForPartition
+                | RDKafkaErrorCode::NotEnoughReplicas
+                | RDKafkaErrorCode::NotEnoughReplicasAfterAppend => ErrorCode::Unavailable,
+                _ => ErrorCode::Internal,
+            }
+        }
+
+        /// Returns true if the record may be delivered on retry.
+        pub fn is_retriable(&self) -> bool {
+            matches!(
+                self,
+                ErrorCode::RateLimited
+                    | ErrorCode::QueueFull
+                    | ErrorCode::Timeout
+                    | ErrorCode::Unavailable
+                    | ErrorCode::TransactionAborted
+            )
+        }
+
+        /// HTTP status code corresponding to the error.
+        pub fn status_code(&self) -> u16 {
+            match self {
+                ErrorCode::RateLimited => 429,
+                ErrorCode::QueueFull => 503,
+                ErrorCode::MessageTooLarge => 413,
+                ErrorCode::UnknownTopic => 404,
+                ErrorCode::Timeout => 504,
+                ErrorCode::Unavailable => 503,
+                ErrorCode::InvalidRequest => 400,
+                ErrorCode::Unauthorized => 401,
+                ErrorCode::Forbidden => 403,
+                ErrorCode::TransactionAborted => 409,
+                ErrorCode::MirrorFailed => 502,
+                ErrorCode::Internal => 500,
+            }
+        }
+    }
+
+    /// PushResponseError describes the result of producing a single record.
+    /// Delivery position is filled only for records delivered in sync mode.
     #[derive(Serialize)]
     pub struct PushResponseError {
         pub error: bool,
+        #[serde(skip_serializing_if = "Option::is_none")]
+        pub code: Option<ErrorCode>,
         pub message: Option<String>,
+        #[serde(skip_serializing_if = "Option::is_none")]
+        pub topic: Option<String>,
+        #[serde(skip_serializing_if = "Option::is_none")]
+        pub partition: Option<i32>,
+        #[serde(skip_serializing_if = "Option::is_none")]
+        pub offset: Option<i64>,
+        /// Timestamp of the message reported by broker, in milliseconds
+        /// since epoch.
+        #[serde(skip_serializing_if = "Option::is_none")]
+        pub timestamp: Option<i64>,
+    }
+
+    impl PushResponseError {
+        pub fn failed(code: ErrorCode, message: String) -> PushResponseError {
+            PushResponseError {
+                error: true,
+                code: Some(code),
+                message: Some(message),
+                topic: None,
+                partition: None,
+                offset: None,
+                timestamp: None,
+            }
+        }
+
+        pub fn delivered(topic: &str, delivery: &producer::Delivery) -> PushResponseError {
+            PushResponseError {
+                error: false,
+                code: None,
+                message: None,
+                topic: Some(topic.to_string()),
+                partition: Some(delivery.partition),
+                offset: Some(delivery.offset),
+                timestamp: delivery.timestamp,
+            }
+        }
     }
 
     #[derive(Serialize)]
@@ -38,123 +424,567 @@ pub(crate) mod requests {
         pub status: String,
         pub errors: Vec<PushResponseError>,
     }
+
+    impl PushResponse {
+        pub fn ok() -> PushResponse {
+            PushResponse {
+                status: super::RESPONSE_STATUS_OK.to_string(),
+                errors: vec![],
+            }
+        }
+
+        /// Appends results of another response. Status becomes failed if
+        /// any of responses failed.
+        pub fn append(&mut self, other: PushResponse) {
+            if other.status != super::RESPONSE_STATUS_OK {
+                self.status = other.status;
+            }
+            self.errors.extend(other.errors);
+        }
+
+        /// HTTP status code of the response. If all failed records share the
+        /// same error code, its status is used; otherwise 500 is returned.
+        /// Records aborted together with failed ones are not taken into
+        /// account.
+        pub fn status_code(&self) -> u16 {
+            let aborted = self
+                .errors
+                .iter()
+                .filter(|e| e.code == Some(ErrorCode::TransactionAborted))
+                .count();
+            let mut codes = self.errors.iter().filter_map(|e| e.code).filter(|code| {
+                aborted == self.errors.len() || *code != ErrorCode::TransactionAborted
+            });
+            let first = match codes.next() {
+                None if self.status == "ok" => return 200,
+                None => return 500,
+                Some(code) => code,
+            };
+            if codes.all(|code| code == first) {
+                return first.status_code();
+            }
+            500
+        }
+    }
+
+    /// NdjsonResponse summarizes the results of NDJSON bulk push.
+    #[derive(Serialize, Default)]
+    pub struct NdjsonResponse {
+        pub status: String,
+        pub accepted: u64,
+        pub failed: u64,
+        /// Numbers of lines (starting from 1) which were not delivered.
+        pub failed_lines: Vec<u64>,
+        /// Reason why reading of the body was interrupted.
+        #[serde(skip_serializing_if = "Option::is_none")]
+        pub message: Option<String>,
+    }
+
+    impl NdjsonResponse {
+        pub fn add(&mut self, line_no: u64, delivered: bool) {
+            if delivered {
+                self.accepted += 1;
+                return;
+            }
+            self.failed += 1;
+            self.failed_lines.push(line_no);
+        }
+
+        /// HTTP status code of the response. Failed records do not change the
+        /// status; 400 is returned only if the body could not be read.
+        pub fn status_code(&self) -> u16 {
+            if self.message.is_some() {
+                return 400;
+            }
+            200
+        }
+    }
 }
 
 pub struct ApiHandler {
     logger: kflog::Logger,
-    kafka_producer: Arc<producer::Producer>,
+    clusters: Arc<Clusters>,
+    routes: Arc<TopicRoutes>,
     ratelimiter: Arc<ratelimit::Limiter>,
+    authorizer: Arc<Authorizer>,
+    topic_policy: Arc<TopicPolicy>,
+    config: ApiConfig,
 }
 
 struct ProduceHelper {
     idx: usize,
-    result: OwnedDeliveryResult,
-    // ratelimit identified if ratelimit occurred.
-    ratelimit: bool,
+    result: Result<producer::Delivery, ProduceError>,
+}
+
+/// ProduceError describes why a record was not delivered.
+/// Errors of checks contain name of the cluster the record was produced to.
+enum ProduceError {
+    Ratelimit(String),
+    // Forbidden means that client is not allowed to produce to the topic.
+    Forbidden(String),
+    // TopicNotAllowed means that the topic is rejected by topics policy.
+    TopicNotAllowed(String),
+    // ClusterForbidden means that client is not allowed to select the
+    // cluster explicitly.
+    ClusterForbidden(String),
+    // ClusterNotAllowed means that explicit choice of the cluster is
+    // rejected by topics policy.
+    ClusterNotAllowed(String),
+    // Invalid contains the reason why record was rejected before producing.
+    Invalid(String),
+    Kafka(KafkaError),
+    // Aborted means that transaction of atomic push was aborted. Contains
+    // the transaction error, if record was aborted because of it.
+    Aborted(Option<KafkaError>),
+    // MirrorFailed contains delivery of the record committed to the primary
+    // cluster and error of the mirror transaction.
+    MirrorFailed(producer::Delivery, KafkaError),
+}
+
+impl ProduceError {
+    fn code(&self) -> ErrorCode {
+        match self {
+            ProduceError::Ratelimit(_) => ErrorCode::RateLimited,
+            ProduceError::Forbidden(_)
+            | ProduceError::TopicNotAllowed(_)
+            | ProduceError::ClusterForbidden(_)
+            | ProduceError::ClusterNotAllowed(_) => ErrorCode::Forbidden,
+            ProduceError::Invalid(_) => ErrorCode::InvalidRequest,
+            ProduceError::Kafka(err) => ErrorCode::from_kafka_error(err),
+            ProduceError::Aborted(_) => ErrorCode::TransactionAborted,
+            ProduceError::MirrorFailed(..) => ErrorCode::MirrorFailed,
+        }
+    }
 }
 
 struct Request {
     logger: kflog::Logger,
-    kafka_producer: Arc<producer::Producer>,
+    clusters: Arc<Clusters>,
+    routes: Arc<TopicRoutes>,
     ratelimiter: Arc<ratelimit::Limiter>,
+    // None if client may produce to any topic.
+    acl: Option<Arc<TopicAcl>>,
+    topic_policy: Arc<TopicPolicy>,
+    config: ApiConfig,
 }
 
 static MESSAGE_RATELIMIT: &str = "ratelimit";
+static MESSAGE_FORBIDDEN: &str = "producing to the topic is not allowed";
+static MESSAGE_TOPIC_NOT_ALLOWED: &str = "topic is not allowed by policy";
+static MESSAGE_CLUSTER_FORBIDDEN: &str = "producing to the cluster is not allowed";
+static MESSAGE_CLUSTER_NOT_ALLOWED: &str = "cluster is not allowed by policy";
+static MESSAGE_TRANSACTION_ABORTED: &str = "transaction was aborted";
+static MESSAGE_MIRROR_FAILED: &str = "committed to primary cluster, but mirror transaction failed";
+static MESSAGE_ATOMIC_DISABLED: &str = "atomic pushes are disabled";
+static MESSAGE_ATOMIC_CLUSTERS: &str =
+    "records of atomic push must be produced to a single cluster";
 
 lazy_static::lazy_static!(
     static ref RATELIMIT_MESSAGES_COUNT: prometheus::IntCounterVec =
         prometheus::register_int_counter_vec!(
             "ratelimit_messages_count",
             "Total number of ratelimited messages",
-            &["topic"]
+            &["cluster", "topic"]
+        )
+        .unwrap();
+    static ref ACL_DENIED_MESSAGES_COUNT: prometheus::IntCounterVec =
+        prometheus::register_int_counter_vec!(
+            "acl_denied_messages_count",
+            "Total number of messages denied by topic ACLs",
+            &["client", "cluster"]
+        )
+        .unwrap();
+    // NOTE: topic is not used as label, because denied topics are usually
+    // made up by mistake and are not limited in number.
+    static ref TOPIC_POLICY_DENIED_MESSAGES_COUNT: prometheus::IntCounterVec =
+        prometheus::register_int_counter_vec!(
+            "topic_policy_denied_messages_count",
+            "Total number of messages denied by topics policy",
+            &["cluster"]
         )
         .unwrap();
 );
 
+/// PreparedRecord is a checked and decoded record, ready to be produced.
+struct PreparedRecord<'a> {
+    record: &'a requests::Record,
+    cluster: Arc<Cluster>,
+    data: Option<Cow<'a, [u8]>>,
+    key: Option<Cow<'a, [u8]>>,
+    // Taken by the message.
+    headers: Option<rdkafka::message::OwnedHeaders>,
+    timestamp: Option<i64>,
+}
+
+impl PreparedRecord<'_> {
+    fn message(&mut self) -> producer::Message<'_> {
+        producer::Message {
+            topic: &self.record.topic,
+            payload: self.data.as_deref(),
+            key: self.key.as_deref(),
+            partition: self.record.partition,
+            headers: self.headers.take(),
+            timestamp: self.timestamp,
+        }
+    }
+}
+
+fn now_ms() -> i64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_millis() as i64)
+        .unwrap_or_default()
+}
+
 impl Request {
     pub(crate) fn new(
         logger: kflog::Logger,
-        kafka_producer: Arc<producer::Producer>,
+        clusters: Arc<Clusters>,
+        routes: Arc<TopicRoutes>,
         ratelimiter: Arc<ratelimit::Limiter>,
+        acl: Option<Arc<TopicAcl>>,
+        topic_policy: Arc<TopicPolicy>,
+        config: ApiConfig,
     ) -> Request {
         Request {
             logger,
-            kafka_producer,
+            clusters,
+            routes,
             ratelimiter,
+            acl,
+            topic_policy,
+            config,
         }
     }
 
-    fn new_ratelimit_error() -> OwnedDeliveryResult {
-        return Err((
-            KafkaError::Canceled,
-            OwnedMessage::new(
-                None,
-                None,
-                "".to_string(),
-                Timestamp::NotAvailable,
-                0,
-                0,
-                None,
-            ),
-        ));
+    /// Checks topics policy and ACL of the client.
+    fn check_topic(&self, topic: &str, cluster: &str) -> Result<(), ProduceError> {
+        if !self.topic_policy.allows(topic) {
+            return Err(ProduceError::TopicNotAllowed(cluster.to_string()));
+        }
+        match &self.acl {
+            Some(acl) if !acl.allows(topic) => Err(ProduceError::Forbidden(cluster.to_string())),
+            _ => Ok(()),
+        }
+    }
+
+    fn check_ratelimit(&self, topic: &String, cluster: &str) -> Result<(), ProduceError> {
+        match self.ratelimiter.check(topic) {
+            Err(e) => {
+                slog::info!(
+                    self.logger,
+                    "got error when tried to check ratelimit";
+                    "topic" => topic,
+                    "error" => e,
+                );
+                Err(ProduceError::Ratelimit(cluster.to_string()))
+            }
+            Ok(false) => Err(ProduceError::Ratelimit(cluster.to_string())),
+            Ok(true) => Ok(()),
+        }
+    }
+
+    /// Returns name of the cluster records of the topic are produced to and
+    /// the cluster itself. Explicit cluster of the record takes precedence
+    /// over topic routes, if it is allowed by topics policy and ACL of the
+    /// client.
+    fn cluster<'a>(
+        &'a self,
+        topic: &str,
+        cluster: Option<&'a str>,
+    ) -> Result<(&'a str, Arc<Cluster>), ProduceError> {
+        let routed = self.routes.cluster(topic);
+        let name = cluster.unwrap_or(routed);
+        let found = self
+            .clusters
+            .get(name)
+            .cloned()
+            .ok_or_else(|| ProduceError::Invalid(format!("unknown cluster '{}'", name)))?;
+        if name != routed {
+            if !self.topic_policy.allows_cluster(name) {
+                return Err(ProduceError::ClusterNotAllowed(name.to_string()));
+            }
+            if let Some(acl) = &self.acl {
+                if !acl.allows_cluster(name) {
+                    return Err(ProduceError::ClusterForbidden(name.to_string()));
+                }
+            }
+        }
+        Ok((name, found))
+    }
+
+    async fn send_message(
+        &self,
+        cluster: &Cluster,
+        message: producer::Message<'_>,
+        timeout: Duration,
+    ) -> Result<producer::Delivery, ProduceError> {
+        cluster
+            .send(message, timeout)
+            .await
+            .map_err(|(err, _)| ProduceError::Kafka(err))
     }
 
-    fn check_ratelimit(&self, topic: &String) -> Result<(), ProduceHelper> {
-        let ratelimit_result = self.ratelimiter.check(topic);
-        if ratelimit_result.is_err() {
-            slog::info!(
+    /// Converts result of producing a record into its response, recording
+    /// logs and metrics for failed records.
+    fn to_response(
+        &self,
+        topic: &str,
+        result: Result<producer::Delivery, ProduceError>,
+    ) -> PushResponseError {
+        let err = match result {
+            Ok(delivery) => return PushResponseError::delivered(topic, &delivery),
+            Err(err) => err,
+        };
+
+        let code = err.code();
+        match err {
+            ProduceError::Ratelimit(cluster) => {
+                // TODO(shmel1k): think about moving this stats recording
+                // to some other place.
+                RATELIMIT_MESSAGES_COUNT
+                    .with_label_values(&[&cluster, topic])
+                    .inc();
+
+                slog::warn!(
+                    self.logger,
+                    "message was not sent due to ratelimit overflow";
+                    "cluster" => cluster,
+                    "topic" => topic,
+                );
+                PushResponseError::failed(code, MESSAGE_RATELIMIT.to_string())
+            }
+            ProduceError::Forbidden(ref cluster) | ProduceError::ClusterForbidden(ref cluster) => {
+                let client = self
+                    .acl
+                    .as_ref()
+                    .map(|acl| acl.client())
+                    .unwrap_or_default();
+                ACL_DENIED_MESSAGES_COUNT
+                    .with_label_values(&[client, cluster])
+                    .inc();
+
+                slog::warn!(
+                    self.logger,
+                    "message was not sent due to topic acl";
+                    "client" => client,
+                    "cluster" => cluster,
+                    "topic" => topic,
+                );
+                let message = if matches!(err, ProduceError::Forbidden(_)) {
+                    MESSAGE_FORBIDDEN
+                } else {
+                    MESSAGE_CLUSTER_FORBIDDEN
+                };
+                PushResponseError::failed(code, message.to_string())
+            }
+            ProduceError::TopicNotAllowed(ref cluster)
+            | ProduceError::ClusterNotAllowed(ref cluster) => {
+                TOPIC_POLICY_DENIED_MESSAGES_COUNT
+                    .with_label_values(&[cluster])
+                    .inc();
+
+                slog::warn!(
+                    self.logger,
+                    "message was not sent due to topics policy";
+                    "cluster" => cluster,
+                    "topic" => topic,
+                );
+                let message = if matches!(err, ProduceError::TopicNotAllowed(_)) {
+                    MESSAGE_TOPIC_NOT_ALLOWED
+                } else {
+                    MESSAGE_CLUSTER_NOT_ALLOWED
+                };
+                PushResponseError::failed(code, message.to_string())
+            }
+            ProduceError::Invalid(reason) => {
+                slog::warn!(
+                    self.logger,
+                    "message was not sent due to invalid record";
+                    "topic" => topic,
+                    "error" => &reason,
+                );
+                PushResponseError::failed(code, reason)
+            }
+            ProduceError::Kafka(err) => {
+                let err_str = err.to_string();
+                slog::error!(
+                    self.logger,
+                    "got error when tried to send message";
+                    "error" => &err_str,
+                    "topic" => topic,
+                );
+                PushResponseError::failed(code, err_str)
+            }
+            // NOTE: transaction error is logged once per push.
+            ProduceError::Aborted(err) => {
+                let message = match err {
+                    None => MESSAGE_TRANSACTION_ABORTED.to_string(),
+                    Some(err) => format!("{}: {}", MESSAGE_TRANSACTION_ABORTED, err),
+                };
+                PushResponseError::failed(code, message)
+            }
+            // NOTE: position of the primary cluster is kept, so that the
+            // client knows the record is delivered there.
+            ProduceError::MirrorFailed(delivery, err) => {
+                let mut response = PushResponseError::delivered(topic, &delivery);
+                response.error = true;
+                response.code = Some(code);
+                response.message = Some(format!("{}: {}", MESSAGE_MIRROR_FAILED, err));
+                response
+            }
+        }
+    }
+
+    /// Checks the record and decodes it for producing.
+    fn prepare_record<'a>(
+        &self,
+        record: &'a requests::Record,
+    ) -> Result<PreparedRecord<'a>, ProduceError> {
+        let (name, cluster) = self.cluster(&record.topic, record.cluster.as_deref())?;
+        self.check_topic(&record.topic, name)?;
+        self.check_ratelimit(&record.topic, name)?;
+
+        let decoded = record.decode().and_then(|(data, key)| {
+            let headers = record.decode_headers(
+                self.config.max_headers_count(),
+                self.config.max_headers_bytes(),
+            )?;
+            let timestamp =
+                record.decode_timestamp(now_ms(), self.config.max_timestamp_skew_ms())?;
+            Ok((data, key, headers, timestamp))
+        });
+        let (data, key, headers, timestamp) = decoded.map_err(ProduceError::Invalid)?;
+        Ok(PreparedRecord {
+            record,
+            cluster,
+            data,
+            key,
+            headers,
+            timestamp,
+        })
+    }
+
+    async fn produce_record(
+        &self,
+        record: &requests::Record,
+        timeout: Duration,
+    ) -> Result<producer::Delivery, ProduceError> {
+        let mut prepared = self.prepare_record(record)?;
+        let cluster = prepared.cluster.clone();
+        self.send_message(&cluster, prepared.message(), timeout)
+            .await
+    }
+
+    /// Produces records in a single transaction. Nothing is produced if any
+    /// of records is rejected. Err is returned if the transaction was not
+    /// committed.
+    async fn produce_atomic(
+        &self,
+        records: &[requests::Record],
+        timeout: Duration,
+    ) -> Result<Vec<PushResponseError>, Vec<PushResponseError>> {
+        let prepared = records
+            .iter()
+            .map(|record| self.prepare_record(record))
+            .collect::<Vec<_>>();
+
+        let results = if prepared.iter().any(|p| p.is_err()) {
+            prepared
+                .into_iter()
+                .map(|p| p.and(Err(ProduceError::Aborted(None))))
+                .collect()
+        } else {
+            let invalid = |message: &str| {
+                records
+                    .iter()
+                    .map(|_| Err(ProduceError::Invalid(message.to_string())))
+                    .collect()
+            };
+            // NOTE: records are never empty, see push_async.
+            let mut prepared = prepared.into_iter().flatten().collect::<Vec<_>>();
+            let cluster = prepared[0].cluster.clone();
+            if prepared.iter().any(|p| !Arc::ptr_eq(&p.cluster, &cluster)) {
+                invalid(MESSAGE_ATOMIC_CLUSTERS)
+            } else {
+                let messages = prepared.iter_mut().map(|p| p.message()).collect();
+                match cluster.send_transaction(messages, timeout).await {
+                    None => invalid(MESSAGE_ATOMIC_DISABLED),
+                    Some(outcome) => self.transaction_results(outcome),
+                }
+            }
+        };
+
+        let committed = results.iter().all(|r: &Result<_, _>| r.is_ok());
+        let responses = records
+            .iter()
+            .zip(results)
+            .map(|(record, result)| self.to_response(&record.topic, result))
+            .collect();
+        if committed {
+            return Ok(responses);
+        }
+        Err(responses)
+    }
+
+    /// Converts outcome of transaction into per-record results. Records
+    /// which failed keep their own error, others are reported as aborted.
+    /// Committed records fail with their position if the mirror failed.
+    fn transaction_results(
+        &self,
+        outcome: producer::TransactionOutcome,
+    ) -> Vec<Result<producer::Delivery, ProduceError>> {
+        let committed = outcome.committed();
+        if let Some(err) = &outcome.error {
+            slog::error!(
                 self.logger,
-                "got error when tried to check ratelimit";
-                "topic" => topic,
-                "error" => ratelimit_result.err(),
+                "transaction of atomic push was aborted";
+                "error" => err.to_string(),
             );
-            return Err(ProduceHelper {
-                idx: 0,
-                result: Request::new_ratelimit_error(),
-                ratelimit: true,
-            });
         }
-        if !ratelimit_result.unwrap() {
-            return Err(ProduceHelper {
-                idx: 0,
-                result: Request::new_ratelimit_error(),
-                ratelimit: true,
-            });
+        if let Some(err) = &outcome.mirror_error {
+            slog::error!(
+                self.logger,
+                "mirror transaction of committed atomic push failed";
+                "error" => err.to_string(),
+            );
         }
-
-        Ok(())
+        let error = outcome.error;
+        let mirror_error = outcome.mirror_error;
+        outcome
+            .results
+            .into_iter()
+            .map(|result| match result {
+                Ok(delivery) if committed => match &mirror_error {
+                    None => Ok(delivery),
+                    Some(err) => Err(ProduceError::MirrorFailed(delivery, err.clone())),
+                },
+                Ok(_) => Err(ProduceError::Aborted(error.clone())),
+                // NOTE: if transaction could not be started, its error is
+                // reported as error of every record.
+                Err((err, _)) if Some(&err) == error.as_ref() => {
+                    Err(ProduceError::Aborted(Some(err)))
+                }
+                Err((err, _)) => Err(ProduceError::Kafka(err)),
+            })
+            .collect()
     }
 
+    /// Produces records and returns per-record results. Err is returned if
+    /// at least one record was not delivered.
     async fn produce_records(
         &self,
         records: &Vec<requests::Record>,
-    ) -> Result<(), Vec<PushResponseError>> {
+        timeout: Duration,
+    ) -> Result<Vec<PushResponseError>, Vec<PushResponseError>> {
         let futures = records
             .iter()
             .enumerate()
             .map(|record| async move {
-                let ratelimit_result = self.check_ratelimit(&record.1.topic);
-                if ratelimit_result.is_err() {
-                    let mut err = ratelimit_result.unwrap_err();
-                    err.idx = record.0 as usize;
-                    return err;
-                }
-
-                let result = self
-                    .kafka_producer
-                    .clone()
-                    .send(
-                        &record.1.topic,
-                        &record.1.data,
-                        record.1.key.as_ref(),
-                        record.1.partition,
-                        Duration::from_millis(100),
-                    )
-                    .await;
+                let result = self.produce_record(record.1, timeout).await;
                 ProduceHelper {
-                    idx: record.0 as usize,
+                    idx: record.0,
                     result,
-                    ratelimit: false,
                 }
             })
             .collect::<Vec<_>>();
@@ -164,66 +994,82 @@ impl Request {
         let mut error_vec = vec![];
         for future in futures {
             let f = future.await;
-            if f.ratelimit {
-                // TODO(shmel1k): think about moving this stats recording
-                // to some other place.
-                RATELIMIT_MESSAGES_COUNT
-                    .with_label_values(&[&records[f.idx].topic])
-                    .inc();
-
-                slog::warn!(
-                    self.logger,
-                    "message was not sent due to ratelimit overflow";
-                    "topic" => &records[f.idx].topic,
-                );
-                error_vec.push(PushResponseError {
-                    error: true,
-                    message: Some(MESSAGE_RATELIMIT.to_string()),
-                });
-                has_errors = true;
-                continue;
-            }
-            if !f.result.is_err() {
-                error_vec.push(PushResponseError {
-                    error: false,
-                    message: None,
-                });
-                continue;
-            }
-
-            let (err, _) = f.result.unwrap_err();
-            let err_str = err.to_string();
-            let topic = &records[f.idx].topic;
-            slog::error!(
-                self.logger,
-                "got error when tried to send message";
-                "error" => &err_str,
-                "topic" => topic,
-            );
-            error_vec.push(PushResponseError {
-                error: true,
-                message: Option::Some(err_str),
-            });
-            has_errors = true;
+            has_errors |= f.result.is_err();
+            error_vec.push(self.to_response(&records[f.idx].topic, f.result));
         }
 
         if has_errors {
             return Err(error_vec);
         }
-        Ok(())
+        Ok(error_vec)
+    }
+
+    pub(crate) async fn push_record(
+        &self,
+        record: &requests::Record,
+        timeout: Duration,
+    ) -> PushResponseError {
+        let result = self.produce_record(record, timeout).await;
+        self.to_response(&record.topic, result)
     }
 
     pub(crate) async fn push_async(
         &self,
         data: &requests::PushRequest,
-    ) -> Result<(), Vec<PushResponseError>> {
+    ) -> Result<Vec<PushResponseError>, Vec<PushResponseError>> {
         if data.records.is_empty() {
-            return Ok(());
+            return Ok(vec![]);
         }
 
         // NOTE(shmel1k): possible API improvement. Add
         // msg_id for each unique message sent.
-        self.produce_records(&data.records).await
+        let timeout = self.config.delivery_timeout(data.timeout_ms);
+        if data.atomic.unwrap_or_default() {
+            return self.produce_atomic(&data.records, timeout).await;
+        }
+        self.produce_records(&data.records, timeout).await
+    }
+
+    pub(crate) async fn push_raw(&self, record: &requests::RawRecord) -> PushResponseError {
+        let result = self.produce_raw(record).await;
+        self.to_response(&record.topic, result)
+    }
+
+    async fn produce_raw(
+        &self,
+        record: &requests::RawRecord,
+    ) -> Result<producer::Delivery, ProduceError> {
+        let (name, cluster) = self.cluster(&record.topic, None)?;
+        self.check_topic(&record.topic, name)?;
+        self.check_ratelimit(&record.topic, name)?;
+
+        let headers = if record.headers.is_empty() {
+            None
+        } else {
+            let headers = record
+                .headers
+                .iter()
+                .map(|(k, v)| (k.as_str(), Cow::Borrowed(v.as_slice())))
+                .collect::<Vec<_>>();
+            let headers = requests::build_headers(
+                &headers,
+                self.config.max_headers_count(),
+                self.config.max_headers_bytes(),
+            )
+            .map_err(ProduceError::Invalid)?;
+            Some(headers)
+        };
+
+        let message = producer::Message {
+            topic: &record.topic,
+            payload: Some(&record.payload),
+            key: record.key.as_deref(),
+            partition: record.partition,
+            headers,
+            timestamp: None,
+        };
+        let timeout = self.config.delivery_timeout(record.timeout_ms);
+        self.send_message(&cluster, message, timeout).await
     }
 }
 
@@ -235,16 +1081,33 @@ impl ApiHandler {
     //    Uuid::new_v4().to_string()
     //}
 
-    pub async fn handle_push(&self, req: requests::PushRequest) -> requests::PushResponse {
-        let request = Request::new(
-            self.logger.clone(),
-            self.kafka_producer.clone(),
+    fn new_request(&self, client: Option<ClientIdentity>) -> Request {
+        let acl = self.authorizer.topic_acl(client.as_ref());
+        let logger = match client {
+            None => self.logger.clone(),
+            Some(client) => Arc::new(self.logger.new(slog::o!("client_id" => client.name))),
+        };
+        Request::new(
+            logger,
+            self.clusters.clone(),
+            self.routes.clone(),
             self.ratelimiter.clone(),
-        );
+            acl,
+            self.topic_policy.clone(),
+            self.config.clone(),
+        )
+    }
 
-        if !req.wait_for_send.unwrap_or_default() {
+    /// Produces a single record which payload was passed as raw request body.
+    pub async fn handle_raw(
+        &self,
+        record: requests::RawRecord,
+        client: Option<ClientIdentity>,
+    ) -> requests::PushResponse {
+        let request = self.new_request(client);
+        if !record.wait_for_send {
             tokio::spawn(async move {
-                let _ = request.push_async(&req).await;
+                let _ = request.push_raw(&record).await;
             });
             return requests::PushResponse {
                 status: RESPONSE_STATUS_OK.to_string(),
@@ -252,17 +1115,47 @@ impl ApiHandler {
             };
         }
 
-        // TODO(a.petrukhin): return back after context implementation.
-        // let req_id = request_id_cloned.clone();
-        let await_result = request.push_async(&req).await;
-        if !await_result.is_err() {
+        let result = request.push_raw(&record).await;
+        let status = if result.error {
+            RESPONSE_STATUS_ERR
+        } else {
+            RESPONSE_STATUS_OK
+        };
+        requests::PushResponse {
+            status: status.to_string(),
+            errors: vec![result],
+        }
+    }
+
+    pub async fn handle_push(
+        &self,
+        req: requests::PushRequest,
+        client: Option<ClientIdentity>,
+    ) -> requests::PushResponse {
+        let request = self.new_request(client);
+
+        if !req.wait_for_send.unwrap_or_default() {
+            tokio::spawn(async move {
+                let _ = request.push_async(&req).await;
+            });
             return requests::PushResponse {
                 status: RESPONSE_STATUS_OK.to_string(),
                 errors: vec![],
             };
         }
 
-        let err = await_result.unwrap_err();
+        // TODO(a.petrukhin): return back after context implementation.
+        // let req_id = request_id_cloned.clone();
+        let await_result = request.push_async(&req).await;
+        let err = match await_result {
+            Ok(delivered) => {
+                return requests::PushResponse {
+                    status: RESPONSE_STATUS_OK.to_string(),
+                    errors: delivered,
+                }
+            }
+            Err(err) => err,
+        };
         for e in err.iter() {
             if e.message.is_none() {
                 continue;
@@ -283,15 +1176,327 @@ impl ApiHandler {
         }
     }
 
+    /// Produces records of newline-delimited JSON body as soon as their lines
+    /// are read, keeping at most `api.ndjson_max_in_flight` records in flight.
+    pub async fn handle_ndjson<S, B, E>(
+        &self,
+        mut body: S,
+        timeout_ms: Option<u64>,
+        client: Option<ClientIdentity>,
+    ) -> requests::NdjsonResponse
+    where
+        S: Stream<Item = Result<B, E>> + Unpin,
+        B: Buf,
+        E: std::fmt::Display,
+    {
+        let request = self.new_request(client);
+        let timeout = self.config.delivery_timeout(timeout_ms);
+        let max_in_flight = self.config.ndjson_max_in_flight();
+        let mut splitter = ndjson::Splitter::new(self.config.ndjson_max_line_bytes());
+
+        let mut response = requests::NdjsonResponse::default();
+        let mut in_flight = FuturesUnordered::new();
+        let produce = |line_no: u64, record: requests::Record| {
+            let request = &request;
+            async move { (line_no, !request.push_record(&record, timeout).await.error) }
+        };
+
+        // NOTE: lines of a chunk are queued, so that a single chunk of short
+        // lines does not exceed the limit of records in flight. Next chunk is
+        // read only when the queue is empty.
+        let mut pending = VecDeque::new();
+        let mut eof = false;
+        while !eof || !pending.is_empty() {
+            while in_flight.len() < max_in_flight {
+                match pending.pop_front() {
+                    Some((line_no, record)) => in_flight.push(produce(line_no, record)),
+                    None => break,
+                }
+            }
+            tokio::select! {
+                Some((line_no, delivered)) = in_flight.next(), if !in_flight.is_empty() => {
+                    response.add(line_no, delivered);
+                    continue;
+                }
+                chunk = body.next(), if !eof && pending.is_empty() && in_flight.len() < max_in_flight => {
+                    let lines: Vec<ndjson::Line> = match chunk {
+                        None => {
+                            eof = true;
+                            splitter.finish().into_iter().collect()
+                        }
+                        Some(Ok(mut chunk)) => {
+                            let mut lines = vec![];
+                            while chunk.has_remaining() && response.message.is_none() {
+                                let len = chunk.chunk().len();
+                                match splitter.push(chunk.chunk()) {
+                                    Ok(l) => lines.extend(l),
+                                    Err(e) => response.message = Some(e),
+                                }
+                                chunk.advance(len);
+                            }
+                            lines
+                        }
+                        Some(Err(e)) => {
+                            response.message = Some(e.to_string());
+                            vec![]
+                        }
+                    };
+                    if response.message.is_some() {
+                        eof = true;
+                    }
+
+                    for (line_no, record) in lines {
+                        match record {
+                            Ok(record) => pending.push_back((line_no, record)),
+                            Err(e) => {
+                                slog::warn!(
+                                    self.logger,
+                                    "got invalid ndjson line";
+                                    "line" => line_no,
+                                    "error" => e,
+                                );
+                                response.add(line_no, false);
+                            }
+                        }
+                    }
+                }
+            }
+        }
+        while let Some((line_no, delivered)) = in_flight.next().await {
+            response.add(line_no, delivered);
+        }
+
+        if let Some(msg) = &response.message {
+            slog::error!(
+                self.logger,
+                "got error when tried to read ndjson body";
+                "error" => msg,
+            );
+        }
+        response.failed_lines.sort_unstable();
+        response.status = if response.failed == 0 && response.message.is_none() {
+            RESPONSE_STATUS_OK.to_string()
+        } else {
+            RESPONSE_STATUS_ERR.to_string()
+        };
+        response
+    }
+
     pub fn new(
         logger: kflog::Logger,
-        kafka_producer: Arc<producer::Producer>,
+        clusters: Arc<Clusters>,
+        routes: Arc<TopicRoutes>,
         ratelimiter: Arc<ratelimit::Limiter>,
+        authorizer: Arc<Authorizer>,
+        topic_policy: Arc<TopicPolicy>,
+        config: ApiConfig,
     ) -> Arc<ApiHandler> {
         Arc::new(ApiHandler {
             logger,
-            kafka_producer,
+            clusters,
+            routes,
             ratelimiter,
+            authorizer,
+            topic_policy,
+            config,
         })
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use crate::http::api_handler::api::requests::{
+        Encoding, ErrorCode, Header, Headers, PushResponse, PushResponseError, Record, Timestamp,
+    };
+    use crate::kafka::kafka::producer;
+    use rdkafka::error::{KafkaError, RDKafkaErrorCode};
+    use rdkafka::message::Headers as _;
+
+    fn delivery() -> producer::Delivery {
+        producer::Delivery {
+            partition: 0,
+            offset: 1,
+            timestamp: Some(2),
+        }
+    }
+
+    fn new_record(data: &str, key: Option<&str>, encoding: Encoding) -> Record {
+        Record {
+            data: Some(data.as_bytes().to_vec()),
+            topic: String::from("some_topic"),
+            key: key.map(|k| k.as_bytes().to_vec()),
+            partition: None,
+            data_encoding: encoding,
+            key_encoding: encoding,
+            headers: None,
+            timestamp: None,
+            cluster: None,
+        }
+    }
+
+    #[test]
+    fn test_record_decode() {
+        let record = new_record("hello", Some("key"), Encoding::Utf8);
+        let (data, key) = record.decode().unwrap();
+        assert_eq!(data.as_deref(), Some(&b"hello"[..]));
+        assert_eq!(key.unwrap().as_ref(), b"key");
+
+        let record = new_record("AAEC/w==", Some("AP8="), Encoding::Base64);
+        let (data, key) = record.decode().unwrap();
+        assert_eq!(data.as_deref(), Some(&[0u8, 1, 2, 255][..]));
+        assert_eq!(key.unwrap().as_ref(), &[0u8, 255]);
+
+        let record = new_record("000102ff", None, Encoding::Hex);
+        let (data, key) = record.decode().unwrap();
+        assert_eq!(data.as_deref(), Some(&[0u8, 1, 2, 255][..]));
+        assert!(key.is_none());
+    }
+
+    #[test]
+    fn test_record_decode_invalid() {
+        let record = new_record("not base64!", None, Encoding::Base64);
+        assert!(record.decode().is_err());
+
+        let record = new_record("00", Some("zz"), Encoding::Hex);
+        let err = record.decode().unwrap_err();
+        assert!(err.starts_with("failed to decode key"));
+    }
+
+    #[test]
+    fn test_record_decode_headers() {
+        let mut record = new_record("data", None, Encoding::Utf8);
+        assert!(record.decode_headers(1, 1).unwrap().is_none());
+
+        record.headers = Some(Headers::List(vec![
+            Header {
+                key: String::from("trace_id"),
+                value: String::from("abc"),
+                encoding: Encoding::Utf8,
+            },
+            Header {
+                key: String::from("schema"),
+                value: String::from("AP8="),
+                encoding: Encoding::Base64,
+            },
+        ]));
+        let headers = record.decode_headers(2, 64).unwrap().unwrap();
+        assert_eq!(headers.count(), 2);
+        assert_eq!(headers.get(1), Some(("schema", &[0u8, 255][..])));
+
+        assert!(record.decode_headers(1, 64).is_err());
+        assert!(record.decode_headers(2, 10).is_err());
+    }
+
+    #[test]
+    fn test_record_decode_timestamp() {
+        let now_ms = 1_600_000_000_000;
+        let mut record = new_record("data", None, Encoding::Utf8);
+        assert_eq!(record.decode_timestamp(now_ms, 1000).unwrap(), None);
+
+        record.timestamp = Some(Timestamp::Millis(now_ms - 500));
+        assert_eq!(
+            record.decode_timestamp(now_ms, 1000).unwrap(),
+            Some(now_ms - 500)
+        );
+        assert!(record.decode_timestamp(now_ms, 100).is_err());
+        assert!(record.decode_timestamp(now_ms + 10_000, 0).is_ok());
+
+        record.timestamp = Some(Timestamp::Rfc3339(String::from("2020-09-13T12:26:40Z")));
+        assert_eq!(record.decode_timestamp(now_ms, 1000).unwrap(), Some(now_ms));
+
+        record.timestamp = Some(Timestamp::Rfc3339(String::from("yesterday")));
+        assert!(record.decode_timestamp(now_ms, 0).is_err());
+    }
+
+    #[test]
+    fn test_error_code_from_kafka_error() {
+        let err = KafkaError::MessageProduction(RDKafkaErrorCode::QueueFull);
+        assert_eq!(ErrorCode::from_kafka_error(&err), ErrorCode::QueueFull);
+
+        let err = KafkaError::MessageProduction(RDKafkaErrorCode::MessageSizeTooLarge);
+        assert_eq!(
+            ErrorCode::from_kafka_error(&err),
+            ErrorCode::MessageTooLarge
+        );
+
+        let err = KafkaError::MessageProduction(RDKafkaErrorCode::UnknownTopicOrPartition);
+        assert_eq!(ErrorCode::from_kafka_error(&err), ErrorCode::UnknownTopic);
+
+        let err = KafkaError::MessageProduction(RDKafkaErrorCode::MessageTimedOut);
+        assert_eq!(ErrorCode::from_kafka_error(&err), ErrorCode::Timeout);
+
+        assert_eq!(
+            ErrorCode::from_kafka_error(&KafkaError::Canceled),
+            ErrorCode::Internal
+        );
+    }
+
+    #[test]
+    fn test_push_response_status_code() {
+        let mut response = PushResponse {
+            status: String::from("ok"),
+            errors: vec![PushResponseError::delivered("topic", &delivery())],
+        };
+        assert_eq!(response.status_code(), 200);
+
+        response.status = String::from("err");
+        response.errors.push(PushResponseError::failed(
+            ErrorCode::RateLimited,
+            String::from("ratelimit"),
+        ));
+        assert_eq!(response.status_code(), 429);
+
+        response.errors.push(PushResponseError::failed(
+            ErrorCode::Timeout,
+            String::from("timeout"),
+        ));
+        assert_eq!(response.status_code(), 500);
+
+        // records committed to primary cluster must not be retried.
+        assert_eq!(ErrorCode::MirrorFailed.status_code(), 502);
+        assert!(!ErrorCode::MirrorFailed.is_retriable());
+    }
+
+    #[test]
+    fn test_push_response_status_code_aborted() {
+        let aborted = || {
+            PushResponseError::failed(
+                ErrorCode::TransactionAborted,
+                String::from("transaction was aborted"),
+            )
+        };
+        let mut response = PushResponse {
+            status: String::from("err"),
+            errors: vec![aborted(), aborted()],
+        };
+        assert_eq!(response.status_code(), 409);
+
+        // records aborted because of the failed one do not affect status.
+        response.errors.push(PushResponseError::failed(
+            ErrorCode::MessageTooLarge,
+            String::from("too large"),
+        ));
+        assert_eq!(response.status_code(), 413);
+    }
+
+    #[test]
+    fn test_push_response_append() {
+        let mut response = PushResponse::ok();
+        response.append(PushResponse {
+            status: String::from("ok"),
+            errors: vec![PushResponseError::delivered("topic", &delivery())],
+        });
+        assert_eq!(response.status, "ok");
+
+        response.append(PushResponse {
+            status: String::from("err"),
+            errors: vec![PushResponseError::failed(
+                ErrorCode::Timeout,
+                String::from("timeout"),
+            )],
+        });
+        response.append(PushResponse::ok());
+        assert_eq!(response.status, "err");
+        assert_eq!(response.errors.len(), 2);
+    }
+}
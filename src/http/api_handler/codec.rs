@@ -0,0 +1,158 @@
+use crate::http::api_handler::api::requests;
+use crate::http::api_handler::proto;
+use prost::Message;
+use std::convert::TryFrom;
+
+const CONTENT_TYPE_PROTOBUF: &str = "application/x-protobuf";
+const CONTENT_TYPE_MSGPACK: &str = "application/msgpack";
+
+/// BodyFormat is a binary encoding of push request and response. JSON is
+/// handled by warp itself and is not listed here.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum BodyFormat {
+    Protobuf,
+    MsgPack,
+}
+
+impl BodyFormat {
+    pub fn from_content_type(content_type: &str) -> Option<BodyFormat> {
+        let mime = content_type.split(';').next().unwrap_or_default().trim();
+        match mime.to_ascii_lowercase().as_str() {
+            CONTENT_TYPE_PROTOBUF | "application/protobuf" => Some(BodyFormat::Protobuf),
+            CONTENT_TYPE_MSGPACK | "application/x-msgpack" => Some(BodyFormat::MsgPack),
+            _ => None,
+        }
+    }
+
+    pub fn content_type(&self) -> &'static str {
+        match self {
+            BodyFormat::Protobuf => CONTENT_TYPE_PROTOBUF,
+            BodyFormat::MsgPack => CONTENT_TYPE_MSGPACK,
+        }
+    }
+
+    pub fn decode_request(&self, body: &[u8]) -> Result<requests::PushRequest, String> {
+        match self {
+            BodyFormat::Protobuf => proto::PushRequest::decode(body)
+                .map_err(|e| e.to_string())
+                .and_then(requests::PushRequest::try_from),
+            BodyFormat::MsgPack => rmp_serde::from_slice(body).map_err(|e| e.to_string()),
+        }
+    }
+
+    pub fn encode_response(&self, response: &requests::PushResponse) -> Vec<u8> {
+        match self {
+            BodyFormat::Protobuf => proto::PushResponse::from(response).encode_to_vec(),
+            // NOTE: serialization of PushResponse into a map can not fail.
+            BodyFormat::MsgPack => rmp_serde::to_vec_named(response).unwrap_or_default(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::http::api_handler::api::requests::{
+        Encoding, ErrorCode, PushResponse, PushResponseError, Timestamp,
+    };
+    use crate::http::api_handler::codec::BodyFormat;
+    use crate::http::api_handler::proto;
+    use crate::kafka::kafka::producer;
+    use prost::Message;
+
+    #[test]
+    fn test_body_format_from_content_type() {
+        assert_eq!(
+            BodyFormat::from_content_type("application/x-protobuf"),
+            Some(BodyFormat::Protobuf)
+        );
+        assert_eq!(
+            BodyFormat::from_content_type("application/msgpack; charset=binary"),
+            Some(BodyFormat::MsgPack)
+        );
+        assert_eq!(BodyFormat::from_content_type("application/json"), None);
+    }
+
+    #[test]
+    fn test_decode_protobuf_request() {
+        let req = proto::PushRequest {
+            records: vec![proto::Record {
+                data: vec![0, 1, 2, 255],
+                topic: "topic".to_string(),
+                key: Some(b"key".to_vec()),
+                headers: vec![proto::Header {
+                    key: "trace-id".to_string(),
+                    value: "abc".to_string(),
+                    encoding: proto::Encoding::Utf8 as i32,
+                }],
+                timestamp: Some(proto::record::Timestamp::TimestampMs(42)),
+                ..Default::default()
+            }],
+            wait_for_send: Some(true),
+            timeout_ms: None,
+            atomic: None,
+        };
+
+        let decoded = BodyFormat::Protobuf
+            .decode_request(&req.encode_to_vec())
+            .unwrap();
+        assert_eq!(decoded.wait_for_send, Some(true));
+        let record = &decoded.records[0];
+        assert_eq!(record.topic, "topic");
+        assert_eq!(record.key.as_deref(), Some(&b"key"[..]));
+        assert_eq!(record.data_encoding, Encoding::Utf8);
+        let (data, _) = record.decode().unwrap();
+        assert_eq!(data.as_deref(), Some(&[0u8, 1, 2, 255][..]));
+        assert!(record.headers.is_some());
+        assert!(matches!(record.timestamp, Some(Timestamp::Millis(42))));
+
+        assert!(BodyFormat::Protobuf.decode_request(b"\xff\xff").is_err());
+    }
+
+    #[test]
+    fn test_decode_msgpack_request() {
+        let req = serde_json::json!({
+            "records": [{"topic": "topic", "data": "some_data", "headers": {"a": "b"}}],
+            "wait_for_send": true,
+        });
+        let body = rmp_serde::to_vec_named(&req).unwrap();
+
+        let decoded = BodyFormat::MsgPack.decode_request(&body).unwrap();
+        assert_eq!(decoded.wait_for_send, Some(true));
+        assert_eq!(decoded.records[0].data.as_deref(), Some(&b"some_data"[..]));
+        assert!(decoded.records[0].headers.is_some());
+
+        // {"records": [{"topic": "t", "data": <bin 0xff>}]}
+        let body = b"\x81\xa7records\x91\x82\xa5topic\xa1t\xa4data\xc4\x01\xff";
+        let decoded = BodyFormat::MsgPack.decode_request(body).unwrap();
+        assert_eq!(decoded.records[0].data.as_deref(), Some(&[0xffu8][..]));
+    }
+
+    #[test]
+    fn test_encode_response() {
+        let response = PushResponse {
+            status: "err".to_string(),
+            errors: vec![
+                PushResponseError::delivered(
+                    "topic",
+                    &producer::Delivery {
+                        partition: 1,
+                        offset: 100,
+                        timestamp: Some(42),
+                    },
+                ),
+                PushResponseError::failed(ErrorCode::Timeout, "timeout".to_string()),
+            ],
+        };
+
+        let body = BodyFormat::Protobuf.encode_response(&response);
+        let decoded = proto::PushResponse::decode(body.as_slice()).unwrap();
+        assert_eq!(decoded.status, "err");
+        assert_eq!(decoded.errors[0].offset, Some(100));
+        assert_eq!(decoded.errors[0].timestamp, Some(42));
+        assert_eq!(decoded.errors[1].code, proto::ErrorCode::Timeout as i32);
+
+        let body = BodyFormat::MsgPack.encode_response(&response);
+        let decoded: serde_json::Value = rmp_serde::from_slice(&body).unwrap();
+        assert_eq!(decoded["errors"][1]["code"], "TIMEOUT");
+    }
+}
@@ -1 +1,6 @@
 pub mod api;
+pub mod codec;
+pub mod ndjson;
+pub mod proto;
+pub mod rest_proxy;
+pub mod topics;
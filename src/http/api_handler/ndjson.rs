@@ -0,0 +1,116 @@
+use crate::http::api_handler::api::requests;
+
+/// Parsed line of NDJSON body: line number (starting from 1) and a record or
+/// the reason why the line can not be parsed.
+pub type Line = (u64, Result<requests::Record, String>);
+
+/// Splitter splits chunks of newline-delimited JSON body into records.
+/// Incomplete trailing line is kept until the next chunk arrives.
+pub struct Splitter {
+    buffer: Vec<u8>,
+    line_no: u64,
+    max_line_bytes: usize,
+}
+
+impl Splitter {
+    pub fn new(max_line_bytes: usize) -> Splitter {
+        Splitter {
+            buffer: vec![],
+            line_no: 0,
+            max_line_bytes,
+        }
+    }
+
+    /// Appends chunk to the buffer and returns all complete lines. Err is
+    /// returned if incomplete line exceeds the maximum line size.
+    pub fn push(&mut self, chunk: &[u8]) -> Result<Vec<Line>, String> {
+        self.buffer.extend_from_slice(chunk);
+
+        let mut lines = vec![];
+        let mut start = 0;
+        while let Some(pos) = self.buffer[start..].iter().position(|b| *b == b'\n') {
+            if let Some(line) = self.parse(start, start + pos) {
+                lines.push(line);
+            }
+            start += pos + 1;
+        }
+        self.buffer.drain(..start);
+
+        if self.buffer.len() > self.max_line_bytes {
+            return Err(format!(
+                "line {} exceeds {} bytes",
+                self.line_no + 1,
+                self.max_line_bytes
+            ));
+        }
+        Ok(lines)
+    }
+
+    /// Returns the last line of the body if it is not terminated by newline.
+    pub fn finish(&mut self) -> Option<Line> {
+        if self.buffer.is_empty() {
+            return None;
+        }
+        let line = self.parse(0, self.buffer.len());
+        self.buffer.clear();
+        line
+    }
+
+    fn parse(&mut self, start: usize, end: usize) -> Option<Line> {
+        let mut line = &self.buffer[start..end];
+        if line.ends_with(b"\r") {
+            line = &line[..line.len() - 1];
+        }
+        // NOTE: empty lines are skipped, but still counted, so that line
+        // numbers in the response match the lines of the body.
+        self.line_no += 1;
+        if line.iter().all(u8::is_ascii_whitespace) {
+            return None;
+        }
+        if line.len() > self.max_line_bytes {
+            let err = format!("line exceeds {} bytes", self.max_line_bytes);
+            return Some((self.line_no, Err(err)));
+        }
+        let record = serde_json::from_slice::<requests::Record>(line).map_err(|e| e.to_string());
+        Some((self.line_no, record))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::http::api_handler::ndjson::Splitter;
+
+    #[test]
+    fn test_splitter_push() {
+        let mut splitter = Splitter::new(1024);
+        let lines = splitter
+            .push(b"{\"topic\": \"a\", \"data\": \"1\"}\n{\"topic\": \"b\", ")
+            .unwrap();
+        assert_eq!(lines.len(), 1);
+        assert_eq!(lines[0].0, 1);
+        assert_eq!(lines[0].1.as_ref().unwrap().topic, "a");
+
+        let lines = splitter.push(b"\"data\": \"2\"}\r\n\nnot json\n").unwrap();
+        assert_eq!(lines.len(), 2);
+        assert_eq!(lines[0].0, 2);
+        assert_eq!(lines[0].1.as_ref().unwrap().topic, "b");
+        assert_eq!(lines[1].0, 4);
+        assert!(lines[1].1.is_err());
+
+        splitter
+            .push(b"{\"topic\": \"c\", \"data\": \"3\"}")
+            .unwrap();
+        let (line_no, record) = splitter.finish().unwrap();
+        assert_eq!(line_no, 5);
+        assert_eq!(record.unwrap().topic, "c");
+        assert!(splitter.finish().is_none());
+    }
+
+    #[test]
+    fn test_splitter_max_line_bytes() {
+        let mut splitter = Splitter::new(8);
+        let lines = splitter.push(b"{\"topic\": \"a\"}\n").unwrap();
+        assert!(lines[0].1.is_err());
+        assert!(splitter.push(b"{\"topic\":").is_err());
+    }
+}
@@ -0,0 +1,122 @@
+//! Protobuf messages generated from `proto/kprf.proto` and their conversions
+//! into API requests and responses.
+
+use crate::http::api_handler::api::requests;
+use std::convert::TryFrom;
+
+include!(concat!(env!("OUT_DIR"), "/kprf.rs"));
+
+impl TryFrom<i32> for requests::Encoding {
+    type Error = String;
+
+    fn try_from(value: i32) -> Result<Self, Self::Error> {
+        match Encoding::from_i32(value) {
+            Some(Encoding::Utf8) => Ok(requests::Encoding::Utf8),
+            Some(Encoding::Base64) => Ok(requests::Encoding::Base64),
+            Some(Encoding::Hex) => Ok(requests::Encoding::Hex),
+            None => Err(format!("unknown encoding {}", value)),
+        }
+    }
+}
+
+impl TryFrom<Record> for requests::Record {
+    type Error = String;
+
+    fn try_from(record: Record) -> Result<Self, Self::Error> {
+        let headers = if record.headers.is_empty() {
+            None
+        } else {
+            let headers = record
+                .headers
+                .into_iter()
+                .map(|h| {
+                    Ok(requests::Header {
+                        key: h.key,
+                        value: h.value,
+                        encoding: requests::Encoding::try_from(h.encoding)?,
+                    })
+                })
+                .collect::<Result<Vec<_>, String>>()?;
+            Some(requests::Headers::List(headers))
+        };
+        let timestamp = record.timestamp.map(|ts| match ts {
+            record::Timestamp::TimestampMs(ms) => requests::Timestamp::Millis(ms),
+            record::Timestamp::TimestampRfc3339(s) => requests::Timestamp::Rfc3339(s),
+        });
+
+        Ok(requests::Record {
+            data: Some(record.data),
+            topic: record.topic,
+            key: record.key,
+            partition: record.partition,
+            data_encoding: requests::Encoding::try_from(record.data_encoding)?,
+            key_encoding: requests::Encoding::try_from(record.key_encoding)?,
+            headers,
+            timestamp,
+            cluster: record.cluster,
+        })
+    }
+}
+
+impl TryFrom<PushRequest> for requests::PushRequest {
+    type Error = String;
+
+    fn try_from(req: PushRequest) -> Result<Self, Self::Error> {
+        let records = req
+            .records
+            .into_iter()
+            .map(requests::Record::try_from)
+            .collect::<Result<Vec<_>, String>>()?;
+        Ok(requests::PushRequest {
+            records,
+            wait_for_send: req.wait_for_send,
+            timeout_ms: req.timeout_ms,
+            atomic: req.atomic,
+        })
+    }
+}
+
+impl From<requests::ErrorCode> for ErrorCode {
+    fn from(code: requests::ErrorCode) -> Self {
+        match code {
+            requests::ErrorCode::RateLimited => ErrorCode::RateLimited,
+            requests::ErrorCode::QueueFull => ErrorCode::QueueFull,
+            requests::ErrorCode::MessageTooLarge => ErrorCode::MessageTooLarge,
+            requests::ErrorCode::UnknownTopic => ErrorCode::UnknownTopic,
+            requests::ErrorCode::Timeout => ErrorCode::Timeout,
+            requests::ErrorCode::Unavailable => ErrorCode::Unavailable,
+            requests::ErrorCode::InvalidRequest => ErrorCode::InvalidRequest,
+            requests::ErrorCode::Unauthorized => ErrorCode::Unauthorized,
+            requests::ErrorCode::Forbidden => ErrorCode::Forbidden,
+            requests::ErrorCode::TransactionAborted => ErrorCode::TransactionAborted,
+            requests::ErrorCode::MirrorFailed => ErrorCode::MirrorFailed,
+            requests::ErrorCode::Internal => ErrorCode::Internal,
+        }
+    }
+}
+
+impl From<&requests::PushResponse> for PushResponse {
+    fn from(response: &requests::PushResponse) -> Self {
+        let errors = response
+            .errors
+            .iter()
+            .map(|e| PushResponseError {
+                error: e.error,
+                code: e
+                    .code
+                    .map(|code| ErrorCode::from(code) as i32)
+                    .unwrap_or_default(),
+                message: e.message.clone(),
+                topic: e.topic.clone(),
+                partition: e.partition,
+                offset: e.offset,
+                timestamp: e.timestamp,
+            })
+            .collect();
+
+        PushResponse {
+            status: response.status.clone(),
+            errors,
+        }
+    }
+}
@@ -0,0 +1,232 @@
+use crate::http::api_handler::api::requests;
+use serde::{Deserialize, Serialize};
+
+const CONTENT_TYPE_JSON_V2: &str = "application/vnd.kafka.json.v2+json";
+const CONTENT_TYPE_BINARY_V2: &str = "application/vnd.kafka.binary.v2+json";
+
+/// Content type of REST Proxy v2 responses.
+pub const CONTENT_TYPE_V2: &str = "application/vnd.kafka.v2+json";
+
+/// Error code of a record which can be retried (REST Proxy v2 semantics).
+const ERROR_CODE_RETRIABLE: i32 = 2;
+/// Error code of a record which can not be retried.
+const ERROR_CODE_NON_RETRIABLE: i32 = 1;
+
+/// Error code of a request with incompatible key or value.
+pub const ERROR_CODE_UNPROCESSABLE: i32 = 42201;
+
+/// EmbeddedFormat describes how keys and values are embedded into
+/// Confluent REST Proxy produce request.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum EmbeddedFormat {
+    /// Keys and values are arbitrary JSON values.
+    Json,
+    /// Keys and values are base64-encoded strings.
+    Binary,
+}
+
+impl EmbeddedFormat {
+    pub fn from_content_type(content_type: &str) -> Option<EmbeddedFormat> {
+        let mime = content_type.split(';').next().unwrap_or_default().trim();
+        if mime.eq_ignore_ascii_case(CONTENT_TYPE_JSON_V2) {
+            return Some(EmbeddedFormat::Json);
+        }
+        if mime.eq_ignore_ascii_case(CONTENT_TYPE_BINARY_V2) {
+            return Some(EmbeddedFormat::Binary);
+        }
+        None
+    }
+
+    fn content_type(&self) -> &'static str {
+        match self {
+            EmbeddedFormat::Json => CONTENT_TYPE_JSON_V2,
+            EmbeddedFormat::Binary => CONTENT_TYPE_BINARY_V2,
+        }
+    }
+
+    /// Encodes key or value of the record. Null is encoded as None, so that
+    /// null value produces a tombstone.
+    fn encode(
+        &self,
+        value: serde_json::Value,
+    ) -> Result<(Option<String>, requests::Encoding), String> {
+        if value.is_null() {
+            return Ok((None, requests::Encoding::Utf8));
+        }
+        match self {
+            EmbeddedFormat::Json => Ok((Some(value.to_string()), requests::Encoding::Utf8)),
+            EmbeddedFormat::Binary => match value {
+                serde_json::Value::String(s) => Ok((Some(s), requests::Encoding::Base64)),
+                _ => Err(format!(
+                    "binary key and value must be base64 strings for '{}'",
+                    self.content_type()
+                )),
+            },
+        }
+    }
+}
+
+#[derive(Debug, Deserialize)]
+pub struct ProduceRecord {
+    #[serde(default)]
+    pub key: Option<serde_json::Value>,
+    #[serde(default)]
+    pub value: serde_json::Value,
+    pub partition: Option<i32>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct ProduceRequest {
+    pub records: Vec<ProduceRecord>,
+}
+
+impl ProduceRequest {
+    /// Converts REST Proxy request into synchronous push request. `partition`
+    /// is taken from request path and overrides partitions of the records.
+    pub fn into_push_request(
+        self,
+        topic: &str,
+        partition: Option<i32>,
+        format: EmbeddedFormat,
+    ) -> Result<requests::PushRequest, String> {
+        let mut records = Vec::with_capacity(self.records.len());
+        for r in self.records {
+            let (data, data_encoding) = format.encode(r.value)?;
+            let (key, key_encoding) = match r.key {
+                None => (None, requests::Encoding::Utf8),
+                Some(k) => format.encode(k)?,
+            };
+            records.push(requests::Record {
+                data: data.map(String::into_bytes),
+                topic: topic.to_string(),
+                key: key.map(String::into_bytes),
+                partition: partition.or(r.partition),
+                data_encoding,
+                key_encoding,
+                headers: None,
+                timestamp: None,
+                cluster: None,
+            });
+        }
+
+        Ok(requests::PushRequest {
+            records,
+            wait_for_send: Some(true),
+            timeout_ms: None,
+            atomic: None,
+        })
+    }
+}
+
+#[derive(Serialize)]
+pub struct PartitionOffset {
+    pub partition: Option<i32>,
+    pub offset: Option<i64>,
+    pub error_code: Option<i32>,
+    pub error: Option<String>,
+}
+
+#[derive(Serialize)]
+pub struct ProduceResponse {
+    pub key_schema_id: Option<i32>,
+    pub value_schema_id: Option<i32>,
+    pub offsets: Vec<PartitionOffset>,
+}
+
+impl From<requests::PushResponse> for ProduceResponse {
+    fn from(response: requests::PushResponse) -> Self {
+        let offsets = response
+            .errors
+            .into_iter()
+            .map(|e| {
+                let error_code = e.code.map(|code| {
+                    if code.is_retriable() {
+                        ERROR_CODE_RETRIABLE
+                    } else {
+                        ERROR_CODE_NON_RETRIABLE
+                    }
+                });
+                PartitionOffset {
+                    partition: e.partition,
+                    offset: e.offset,
+                    error_code,
+                    error: e.message,
+                }
+            })
+            .collect();
+
+        ProduceResponse {
+            key_schema_id: None,
+            value_schema_id: None,
+            offsets,
+        }
+    }
+}
+
+#[derive(Serialize)]
+pub struct ErrorResponse {
+    pub error_code: i32,
+    pub message: String,
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::http::api_handler::api::requests::Encoding;
+    use crate::http::api_handler::rest_proxy::{EmbeddedFormat, ProduceRequest};
+
+    #[test]
+    fn test_embedded_format_from_content_type() {
+        assert_eq!(
+            EmbeddedFormat::from_content_type("application/vnd.kafka.json.v2+json"),
+            Some(EmbeddedFormat::Json)
+        );
+        assert_eq!(
+            EmbeddedFormat::from_content_type(
+                "application/vnd.kafka.binary.v2+json; charset=utf-8"
+            ),
+            Some(EmbeddedFormat::Binary)
+        );
+        assert_eq!(EmbeddedFormat::from_content_type("application/json"), None);
+    }
+
+    #[test]
+    fn test_produce_request_into_push_request() {
+        let req: ProduceRequest = serde_json::from_str(
+            r#"{"records": [{"key": {"id": 1}, "value": {"a": "b"}}, {"value": [1, 2], "partition": 3}]}"#,
+        )
+        .unwrap();
+        let push = req
+            .into_push_request("topic", None, EmbeddedFormat::Json)
+            .unwrap();
+        assert_eq!(push.wait_for_send, Some(true));
+        assert_eq!(push.records.len(), 2);
+        assert_eq!(push.records[0].data.as_deref(), Some(&br#"{"a":"b"}"#[..]));
+        assert_eq!(push.records[0].key.as_deref(), Some(&br#"{"id":1}"#[..]));
+        assert_eq!(push.records[1].data.as_deref(), Some(&b"[1,2]"[..]));
+        assert_eq!(push.records[1].partition, Some(3));
+
+        let req: ProduceRequest =
+            serde_json::from_str(r#"{"records": [{"key": "AP8=", "value": "aGVsbG8="}]}"#).unwrap();
+        let push = req
+            .into_push_request("topic", Some(1), EmbeddedFormat::Binary)
+            .unwrap();
+        assert_eq!(push.records[0].data.as_deref(), Some(&b"aGVsbG8="[..]));
+        assert_eq!(push.records[0].data_encoding, Encoding::Base64);
+        assert_eq!(push.records[0].key_encoding, Encoding::Base64);
+        assert_eq!(push.records[0].partition, Some(1));
+
+        // null value is a tombstone.
+        for format in [EmbeddedFormat::Json, EmbeddedFormat::Binary].iter() {
+            let req: ProduceRequest =
+                serde_json::from_str(r#"{"records": [{"key": "AP8=", "value": null}]}"#).unwrap();
+            let push = req.into_push_request("topic", None, *format).unwrap();
+            assert_eq!(push.records[0].data, None);
+            assert!(push.records[0].key.is_some());
+        }
+
+        let req: ProduceRequest = serde_json::from_str(r#"{"records": [{"value": 42}]}"#).unwrap();
+        assert!(req
+            .into_push_request("topic", None, EmbeddedFormat::Binary)
+            .is_err());
+    }
+}
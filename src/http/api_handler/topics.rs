@@ -0,0 +1,138 @@
+use crate::config::{RouteConfig, TopicsConfig};
+use crate::kafka::cluster::DEFAULT_CLUSTER;
+use regex::RegexSet;
+use std::collections::HashSet;
+
+/// TopicPolicy decides whether records may be produced to a topic. It is
+/// applied to every client, in addition to topic ACLs.
+pub struct TopicPolicy {
+    allow: HashSet<String>,
+    allow_patterns: RegexSet,
+    deny: HashSet<String>,
+    deny_patterns: RegexSet,
+    clusters: HashSet<String>,
+}
+
+/// Compiles patterns, so that every pattern matches the whole topic name.
+fn compile(patterns: &[String]) -> Result<RegexSet, String> {
+    RegexSet::new(patterns.iter().map(|p| format!("^(?:{})$", p))).map_err(|e| e.to_string())
+}
+
+impl TopicPolicy {
+    pub fn new(config: &TopicsConfig) -> Result<TopicPolicy, String> {
+        Ok(TopicPolicy {
+            allow: config.allow().iter().cloned().collect(),
+            allow_patterns: compile(config.allow_patterns())?,
+            deny: config.deny().iter().cloned().collect(),
+            deny_patterns: compile(config.deny_patterns())?,
+            clusters: config.clusters().iter().cloned().collect(),
+        })
+    }
+
+    pub fn allows(&self, topic: &str) -> bool {
+        if self.deny.contains(topic) || self.deny_patterns.is_match(topic) {
+            return false;
+        }
+        if self.allow.is_empty() && self.allow_patterns.is_empty() {
+            return true;
+        }
+        self.allow.contains(topic) || self.allow_patterns.is_match(topic)
+    }
+
+    /// Returns true if records may select the cluster explicitly instead of
+    /// the one chosen by routes.
+    pub fn allows_cluster(&self, cluster: &str) -> bool {
+        self.clusters.contains(cluster)
+    }
+}
+
+struct Route {
+    cluster: String,
+    topics: HashSet<String>,
+    topic_patterns: RegexSet,
+}
+
+/// TopicRoutes decides which cluster records of a topic are produced to.
+/// Routes are checked in order, topics without routes are produced to the
+/// default cluster.
+pub struct TopicRoutes {
+    routes: Vec<Route>,
+}
+
+impl TopicRoutes {
+    pub fn new(routes: &[RouteConfig], clusters: &[String]) -> Result<TopicRoutes, String> {
+        let routes = routes
+            .iter()
+            .map(|route| {
+                if !clusters.iter().any(|c| c == route.cluster()) {
+                    return Err(format!(
+                        "route refers to unknown cluster '{}'",
+                        route.cluster()
+                    ));
+                }
+                Ok(Route {
+                    cluster: route.cluster().to_string(),
+                    topics: route.topics().iter().cloned().collect(),
+                    topic_patterns: compile(route.topic_patterns())?,
+                })
+            })
+            .collect::<Result<Vec<_>, String>>()?;
+        Ok(TopicRoutes { routes })
+    }
+
+    pub fn cluster(&self, topic: &str) -> &str {
+        self.routes
+            .iter()
+            .find(|route| route.topics.contains(topic) || route.topic_patterns.is_match(topic))
+            .map(|route| route.cluster.as_str())
+            .unwrap_or(DEFAULT_CLUSTER)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::config::{KafkaProxyConfig, TopicsConfig};
+    use crate::http::api_handler::topics::{TopicPolicy, TopicRoutes};
+
+    #[test]
+    fn test_topic_policy() {
+        let config =
+            KafkaProxyConfig::initialize_config(&String::from("testdata/kafka_config.yaml"))
+                .unwrap();
+        let policy = TopicPolicy::new(&config.get_topics_config()).unwrap();
+        assert!(policy.allows("orders"));
+        assert!(policy.allows("logs.nginx"));
+        assert!(!policy.allows("logs.debug"));
+        assert!(!policy.allows("logs."));
+        assert!(!policy.allows("ordres"));
+        // patterns match the whole topic name.
+        assert!(!policy.allows("php-logs.nginx"));
+        assert!(policy.allows_cluster("billing"));
+        assert!(!policy.allows_cluster("standby"));
+    }
+
+    #[test]
+    fn test_topic_policy_default() {
+        let policy = TopicPolicy::new(&TopicsConfig::default()).unwrap();
+        assert!(policy.allows("orders"));
+        assert!(policy.allows("__consumer_offsets"));
+        assert!(!policy.allows_cluster("default"));
+    }
+
+    #[test]
+    fn test_topic_routes() {
+        let config =
+            KafkaProxyConfig::initialize_config(&String::from("testdata/kafka_config.yaml"))
+                .unwrap();
+        let clusters = vec![String::from("billing"), String::from("default")];
+        let routes = TopicRoutes::new(&config.get_routes_config(), &clusters).unwrap();
+        assert_eq!(routes.cluster("payments"), "billing");
+        assert_eq!(routes.cluster("billing.invoices"), "billing");
+        assert_eq!(routes.cluster("orders"), "default");
+        // patterns match the whole topic name.
+        assert_eq!(routes.cluster("old-billing.invoices"), "default");
+
+        let clusters = vec![String::from("default")];
+        assert!(TopicRoutes::new(&config.get_routes_config(), &clusters).is_err());
+    }
+}
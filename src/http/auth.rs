@@ -0,0 +1,343 @@
+use crate::config::AuthConfig;
+use crate::http::tls::ClientCert;
+use sha2::{Digest, Sha256};
+use std::collections::{HashMap, HashSet};
+use std::sync::Arc;
+
+/// Authentication scheme of API key, matched case-insensitively (RFC 7235).
+const BEARER_SCHEME: &str = "Bearer";
+
+lazy_static::lazy_static! {
+    static ref AUTH_FAILED_COUNT: prometheus::IntCounterVec = prometheus::register_int_counter_vec!(
+        "auth_failed_requests_count",
+        "Total number of requests rejected due to failed authentication",
+        &["reason"]
+    ).unwrap();
+}
+
+/// ClientIdentity is a name of authenticated client.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ClientIdentity {
+    pub name: String,
+}
+
+#[derive(Debug, PartialEq)]
+pub enum AuthError {
+    /// Neither API key nor client certificate was passed.
+    Missing,
+    /// API key is unknown.
+    Invalid,
+    /// Subject of client certificate is unknown.
+    UnknownCertificate,
+}
+
+impl AuthError {
+    pub fn message(&self) -> &'static str {
+        match self {
+            AuthError::Missing => "missing api key",
+            AuthError::Invalid => "invalid api key",
+            AuthError::UnknownCertificate => "unknown client certificate",
+        }
+    }
+}
+
+impl warp::reject::Reject for AuthError {}
+
+/// Extracts API key from `Authorization: Bearer <key>` or `X-Api-Key: <key>`
+/// header values.
+pub fn api_key<'a>(authorization: Option<&'a str>, x_api_key: Option<&'a str>) -> Option<&'a str> {
+    authorization
+        .and_then(|v| {
+            let (scheme, key) = v.trim_start().split_once(' ')?;
+            Some(key).filter(|_| scheme.eq_ignore_ascii_case(BEARER_SCHEME))
+        })
+        .or(x_api_key)
+        .map(str::trim)
+}
+
+/// Authenticator resolves clients by API keys and client certificates
+/// described in config. Only SHA-256 hashes of API keys are stored.
+pub struct Authenticator {
+    enabled: bool,
+    keys: HashMap<Vec<u8>, ClientIdentity>,
+    subjects: HashMap<String, ClientIdentity>,
+}
+
+impl Authenticator {
+    pub fn new(config: &AuthConfig) -> Result<Authenticator, String> {
+        let mut keys = HashMap::new();
+        let mut subjects = HashMap::new();
+        for client in config.clients() {
+            let identity = ClientIdentity {
+                name: client.name().to_string(),
+            };
+            if let Some(hash) = client.key_sha256() {
+                let hash = hex::decode(hash)
+                    .ok()
+                    .filter(|h| h.len() == 32)
+                    .ok_or_else(|| format!("invalid key_sha256 of client '{}'", client.name()))?;
+                keys.insert(hash, identity.clone());
+            }
+            if let Some(subject) = client.cert_subject() {
+                subjects.insert(subject.to_string(), identity);
+            }
+        }
+
+        Ok(Authenticator {
+            enabled: config.enabled(),
+            keys,
+            subjects,
+        })
+    }
+
+    /// Returns identity of the client. None is returned if authentication
+    /// is disabled. API key takes precedence over client certificate.
+    pub fn authenticate(
+        &self,
+        key: Option<&str>,
+        cert: Option<&ClientCert>,
+    ) -> Result<Option<ClientIdentity>, AuthError> {
+        if !self.enabled {
+            return Ok(None);
+        }
+
+        let result = match (key, cert) {
+            (Some(key), _) => self
+                .keys
+                .get(Sha256::digest(key.as_bytes()).as_slice())
+                .ok_or(AuthError::Invalid),
+            (None, Some(cert)) => self
+                .subjects
+                .get(&cert.subject)
+                .ok_or(AuthError::UnknownCertificate),
+            (None, None) => Err(AuthError::Missing),
+        };
+        match result {
+            Ok(identity) => Ok(Some(identity.clone())),
+            Err(e) => {
+                let reason = match e {
+                    AuthError::Missing => "missing",
+                    AuthError::Invalid => "invalid",
+                    AuthError::UnknownCertificate => "unknown_certificate",
+                };
+                AUTH_FAILED_COUNT.with_label_values(&[reason]).inc();
+                Err(e)
+            }
+        }
+    }
+}
+
+/// Matches topic against glob pattern, where '*' matches any sequence of
+/// characters and '?' matches a single character.
+fn glob_match(pattern: &str, topic: &str) -> bool {
+    let (pattern, topic) = (pattern.as_bytes(), topic.as_bytes());
+    let (mut pi, mut ti) = (0, 0);
+    // Positions of the last '*' in pattern and of the topic character
+    // matched right after it.
+    let mut star: Option<(usize, usize)> = None;
+    while ti < topic.len() {
+        if pi < pattern.len() && (pattern[pi] == b'?' || pattern[pi] == topic[ti]) {
+            pi += 1;
+            ti += 1;
+        } else if pi < pattern.len() && pattern[pi] == b'*' {
+            star = Some((pi, ti));
+            pi += 1;
+        } else if let Some((star_pi, star_ti)) = star {
+            pi = star_pi + 1;
+            ti = star_ti + 1;
+            star = Some((star_pi, ti));
+        } else {
+            return false;
+        }
+    }
+    pattern[pi..].iter().all(|c| *c == b'*')
+}
+
+/// TopicAcl is a set of topics a single client is allowed to produce to.
+#[derive(Debug, Default)]
+pub struct TopicAcl {
+    client: String,
+    topics: HashSet<String>,
+    prefixes: Vec<String>,
+    globs: Vec<String>,
+    clusters: HashSet<String>,
+}
+
+impl TopicAcl {
+    pub fn client(&self) -> &str {
+        &self.client
+    }
+
+    pub fn allows(&self, topic: &str) -> bool {
+        self.topics.contains(topic)
+            || self.prefixes.iter().any(|p| topic.starts_with(p.as_str()))
+            || self.globs.iter().any(|g| glob_match(g, topic))
+    }
+
+    /// Returns true if the client may select the cluster explicitly instead
+    /// of the one chosen by routes.
+    pub fn allows_cluster(&self, cluster: &str) -> bool {
+        self.clusters.contains(cluster)
+    }
+}
+
+/// Authorizer resolves topic ACLs of authenticated clients.
+pub struct Authorizer {
+    enabled: bool,
+    acls: HashMap<String, Arc<TopicAcl>>,
+}
+
+impl Authorizer {
+    pub fn new(config: &AuthConfig) -> Result<Authorizer, String> {
+        if config.acl_enabled() && !config.enabled() {
+            return Err(String::from("auth.acl_enabled requires auth.enabled"));
+        }
+
+        // NOTE: several entries of the same client are merged.
+        let mut acls = HashMap::<String, TopicAcl>::new();
+        for acl in config.acls() {
+            let entry = acls
+                .entry(acl.client().to_string())
+                .or_insert_with(|| TopicAcl {
+                    client: acl.client().to_string(),
+                    ..Default::default()
+                });
+            entry.topics.extend(acl.topics().iter().cloned());
+            entry.prefixes.extend(acl.topic_prefixes().iter().cloned());
+            entry.globs.extend(acl.topic_globs().iter().cloned());
+            entry.clusters.extend(acl.clusters().iter().cloned());
+        }
+
+        Ok(Authorizer {
+            enabled: config.acl_enabled(),
+            acls: acls.into_iter().map(|(k, v)| (k, Arc::new(v))).collect(),
+        })
+    }
+
+    /// Returns ACL of the client. None is returned if ACLs are disabled and
+    /// any topic is allowed. Clients without ACLs are not allowed to produce
+    /// to any topic.
+    pub fn topic_acl(&self, client: Option<&ClientIdentity>) -> Option<Arc<TopicAcl>> {
+        if !self.enabled {
+            return None;
+        }
+
+        let name = client.map(|c| c.name.as_str()).unwrap_or_default();
+        let acl = self.acls.get(name).cloned().unwrap_or_else(|| {
+            Arc::new(TopicAcl {
+                client: name.to_string(),
+                ..Default::default()
+            })
+        });
+        Some(acl)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::config::KafkaProxyConfig;
+    use crate::http::auth::{
+        api_key, glob_match, AuthError, Authenticator, Authorizer, ClientIdentity,
+    };
+    use crate::http::tls::ClientCert;
+
+    fn new_authenticator() -> Authenticator {
+        let config =
+            KafkaProxyConfig::initialize_config(&String::from("testdata/kafka_config.yaml"))
+                .unwrap();
+        Authenticator::new(&config.get_auth_config()).unwrap()
+    }
+
+    #[test]
+    fn test_api_key() {
+        assert_eq!(api_key(Some("Bearer abc"), None), Some("abc"));
+        assert_eq!(api_key(Some("bearer abc"), None), Some("abc"));
+        assert_eq!(api_key(Some("BEARER  abc"), None), Some("abc"));
+        assert_eq!(api_key(Some("Bearerabc"), None), None);
+        assert_eq!(api_key(Some("Basic abc"), Some("def")), Some("def"));
+        assert_eq!(api_key(None, Some("def")), Some("def"));
+        assert_eq!(api_key(Some("Basic abc"), None), None);
+    }
+
+    #[test]
+    fn test_authenticate() {
+        let auth = new_authenticator();
+
+        let identity = auth
+            .authenticate(Some("secret-key"), None)
+            .unwrap()
+            .unwrap();
+        assert_eq!(identity.name, "php-backend");
+        assert_eq!(
+            auth.authenticate(Some("wrong-key"), None),
+            Err(AuthError::Invalid)
+        );
+        assert_eq!(auth.authenticate(None, None), Err(AuthError::Missing));
+
+        let cert = ClientCert {
+            subject: String::from("O=city-mobil, CN=php-backend"),
+        };
+        let identity = auth.authenticate(None, Some(&cert)).unwrap().unwrap();
+        assert_eq!(identity.name, "php-backend");
+
+        let cert = ClientCert {
+            subject: String::from("CN=unknown"),
+        };
+        assert_eq!(
+            auth.authenticate(None, Some(&cert)),
+            Err(AuthError::UnknownCertificate)
+        );
+    }
+
+    #[test]
+    fn test_authenticate_disabled() {
+        let config =
+            KafkaProxyConfig::initialize_config(&String::from("testdata/ratelimit.yaml")).unwrap();
+        let auth = Authenticator::new(&config.get_auth_config()).unwrap();
+        assert_eq!(auth.authenticate(None, None), Ok(None));
+
+        let authorizer = Authorizer::new(&config.get_auth_config()).unwrap();
+        assert!(authorizer.topic_acl(None).is_none());
+    }
+
+    #[test]
+    fn test_glob_match() {
+        assert!(glob_match("events-*", "events-"));
+        assert!(glob_match("events-*", "events-clicks"));
+        assert!(glob_match("*-v?", "clicks-v1"));
+        assert!(glob_match("a*b*c", "aXbYbZc"));
+        assert!(glob_match("*", ""));
+        assert!(!glob_match("events-*", "event-clicks"));
+        assert!(!glob_match("*-v?", "clicks-v10"));
+        assert!(!glob_match("a*b*c", "aXbYbZ"));
+    }
+
+    #[test]
+    fn test_topic_acl() {
+        let config =
+            KafkaProxyConfig::initialize_config(&String::from("testdata/kafka_config.yaml"))
+                .unwrap();
+        let authorizer = Authorizer::new(&config.get_auth_config()).unwrap();
+
+        let client = ClientIdentity {
+            name: String::from("php-backend"),
+        };
+        let acl = authorizer.topic_acl(Some(&client)).unwrap();
+        assert_eq!(acl.client(), "php-backend");
+        assert!(acl.allows("orders"));
+        assert!(!acl.allows("orders-v2"));
+        assert!(acl.allows("logs.nginx"));
+        assert!(!acl.allows("logs"));
+        assert!(acl.allows("events-clicks-v1"));
+        assert!(!acl.allows("events-clicks-v10"));
+        assert!(acl.allows_cluster("billing"));
+        assert!(!acl.allows_cluster("standby"));
+
+        let client = ClientIdentity {
+            name: String::from("unknown"),
+        };
+        let acl = authorizer.topic_acl(Some(&client)).unwrap();
+        assert!(!acl.allows("orders"));
+        assert!(!acl.allows_cluster("billing"));
+    }
+}
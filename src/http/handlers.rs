@@ -1,6 +1,10 @@
 pub mod filter {
     use super::handler;
     use crate::http::api_handler::api::ApiHandler;
+    use crate::http::api_handler::codec::BodyFormat;
+    use crate::http::api_handler::rest_proxy::EmbeddedFormat;
+    use crate::http::auth::{self, Authenticator, ClientIdentity};
+    use crate::http::tls::ClientCert;
     use crate::log::kflog;
     use std::sync::Arc;
     use warp::Filter;
@@ -8,13 +12,127 @@ pub mod filter {
     pub fn new_api(
         logger: kflog::Logger,
         api_handler: Arc<ApiHandler>,
+        authenticator: Arc<Authenticator>,
     ) -> impl Filter<Extract = impl warp::Reply, Error = warp::Rejection> + Clone {
-        return warp::path!("push")
+        // NOTE: client is authenticated before body format is chosen, so that
+        // every request is authenticated once.
+        let push = warp::path!("push")
             .and(warp::post())
-            .and(warp::body::json())
+            .and(with_identity(authenticator.clone()))
+            .and(with_push_body())
+            .and(with_logger(logger.clone()))
+            .and(with_api_handler(api_handler.clone()))
+            .and_then(handler::push);
+
+        let ndjson = warp::path!("push" / "ndjson")
+            .and(warp::post())
+            .and(with_identity(authenticator.clone()))
+            .and(warp::query::<handler::NdjsonQuery>())
+            .and(warp::body::stream())
+            .and(with_logger(logger.clone()))
+            .and(with_api_handler(api_handler.clone()))
+            .and_then(handler::push_ndjson);
+
+        let rest_proxy = with_topic_path()
+            .and(warp::post())
+            .and(with_rest_proxy_format())
+            .and(with_identity(authenticator.clone()))
+            .and(warp::body::bytes())
+            .and(with_logger(logger.clone()))
+            .and(with_api_handler(api_handler.clone()))
+            .and_then(handler::rest_proxy_produce);
+
+        let raw = with_topic_path()
+            .and(warp::post())
+            .and(without_rest_proxy_format())
+            .and(with_identity(authenticator))
+            .and(warp::query::<handler::RawQuery>())
+            .and(warp::header::headers_cloned())
+            .and(warp::body::bytes())
             .and(with_logger(logger))
             .and(with_api_handler(api_handler))
-            .and_then(handler::push);
+            .and_then(handler::push_raw);
+
+        return push
+            .or(ndjson)
+            .or(rest_proxy)
+            .or(raw)
+            .recover(handler::handle_rejection);
+    }
+
+    /// Extracts topic and optional partition from `/topics/{topic}` and
+    /// `/topics/{topic}/partitions/{partition}` paths.
+    fn with_topic_path(
+    ) -> impl Filter<Extract = ((String, Option<i32>),), Error = warp::Rejection> + Clone {
+        let topic = warp::path!("topics" / String).map(|topic| (topic, None));
+        let partition = warp::path!("topics" / String / "partitions" / i32)
+            .map(|topic, partition| (topic, Some(partition)));
+        topic.or(partition).unify()
+    }
+
+    /// Accepts only requests with protobuf or msgpack content types.
+    fn with_body_format() -> impl Filter<Extract = (BodyFormat,), Error = warp::Rejection> + Clone {
+        warp::header::<String>("content-type").and_then(|content_type: String| async move {
+            BodyFormat::from_content_type(&content_type).ok_or_else(warp::reject::not_found)
+        })
+    }
+
+    /// Extracts body of push request, which is decoded later if it is
+    /// encoded with protobuf or msgpack, or JSON otherwise.
+    fn with_push_body(
+    ) -> impl Filter<Extract = (handler::PushBody,), Error = warp::Rejection> + Clone {
+        let encoded = with_body_format()
+            .and(warp::body::bytes())
+            .map(handler::PushBody::Encoded);
+        let json = warp::body::json().map(handler::PushBody::Json);
+        encoded.or(json).unify()
+    }
+
+    /// Accepts only requests with Confluent REST Proxy v2 content types.
+    fn with_rest_proxy_format(
+    ) -> impl Filter<Extract = (EmbeddedFormat,), Error = warp::Rejection> + Clone {
+        warp::header::<String>("content-type").and_then(|content_type: String| async move {
+            EmbeddedFormat::from_content_type(&content_type).ok_or_else(warp::reject::not_found)
+        })
+    }
+
+    /// Rejects requests with Confluent REST Proxy v2 content types, so that
+    /// malformed REST Proxy requests are not produced as raw payloads.
+    fn without_rest_proxy_format() -> impl Filter<Extract = (), Error = warp::Rejection> + Clone {
+        warp::header::optional::<String>("content-type")
+            .and_then(|content_type: Option<String>| async move {
+                match content_type
+                    .as_deref()
+                    .and_then(EmbeddedFormat::from_content_type)
+                {
+                    Some(_) => Err(warp::reject::not_found()),
+                    None => Ok(()),
+                }
+            })
+            .untuple_one()
+    }
+
+    /// Authenticates client by API key or client certificate. Requests of
+    /// unknown clients are rejected with `AuthError`.
+    fn with_identity(
+        authenticator: Arc<Authenticator>,
+    ) -> impl Filter<Extract = (Option<ClientIdentity>,), Error = warp::Rejection> + Clone {
+        warp::header::optional::<String>("authorization")
+            .and(warp::header::optional::<String>("x-api-key"))
+            .and(warp::ext::optional::<ClientCert>())
+            .and_then(
+                move |authorization: Option<String>,
+                      x_api_key: Option<String>,
+                      cert: Option<ClientCert>| {
+                    let authenticator = authenticator.clone();
+                    async move {
+                        let key = auth::api_key(authorization.as_deref(), x_api_key.as_deref());
+                        authenticator
+                            .authenticate(key, cert.as_ref())
+                            .map_err(warp::reject::custom)
+                    }
+                },
+            )
     }
 
     fn with_api_handler(
@@ -23,21 +141,52 @@ pub mod filter {
         warp::any().map(move || handler.clone())
     }
 
+    /// Provides logger of the request. Subject of client certificate is
+    /// added to every record of the logger if client was authenticated
+    /// with mutual TLS.
     fn with_logger(
         logger: kflog::Logger,
     ) -> impl Filter<Extract = (kflog::Logger,), Error = std::convert::Infallible> + Clone {
-        warp::any().map(move || logger.clone())
+        warp::ext::optional::<ClientCert>().map(move |cert: Option<ClientCert>| match cert {
+            None => logger.clone(),
+            Some(cert) => Arc::new(logger.new(slog::o!("client" => cert.subject))),
+        })
     }
 }
 
 mod handler {
     use crate::http::api_handler::api::{requests, ApiHandler};
+    use crate::http::api_handler::codec::BodyFormat;
+    use crate::http::api_handler::rest_proxy;
+    use crate::http::auth::{AuthError, ClientIdentity};
     use crate::log::kflog;
+    use bytes::Buf;
+    use futures::Stream;
+    use serde::Deserialize;
     use std::convert::Infallible;
     use std::sync::Arc;
     use std::time::SystemTime;
     use uuid::Uuid;
-    use warp::Reply;
+    use warp::filters::body::BodyDeserializeError;
+    use warp::http::HeaderMap;
+    use warp::{Rejection, Reply};
+
+    const HEADER_KAFKA_KEY: &str = "x-kafka-key";
+    const HEADER_KAFKA_HEADER_PREFIX: &str = "x-kafka-header-";
+
+    /// Query parameters of raw body push request.
+    #[derive(Debug, Deserialize)]
+    pub struct RawQuery {
+        key: Option<String>,
+        wait_for_send: Option<bool>,
+        timeout_ms: Option<u64>,
+    }
+
+    /// Query parameters of NDJSON bulk push request.
+    #[derive(Debug, Deserialize)]
+    pub struct NdjsonQuery {
+        timeout_ms: Option<u64>,
+    }
 
     const MAX_NORMAL_REQUEST_TIME: f64 = 0.01; // todo extract to config
 
@@ -54,11 +203,66 @@ mod handler {
         Uuid::new_v4().to_string()
     }
 
+    /// Body of push request.
+    pub enum PushBody {
+        /// Body encoded with protobuf or msgpack.
+        Encoded(BodyFormat, bytes::Bytes),
+        Json(requests::PushRequest),
+    }
+
     pub async fn push(
-        req: requests::PushRequest,
+        client: Option<ClientIdentity>,
+        body: PushBody,
         logger: kflog::Logger,
         handler: Arc<ApiHandler>,
-    ) -> Result<impl Reply, Infallible> {
+    ) -> Result<warp::reply::Response, Infallible> {
+        let req = match body {
+            PushBody::Json(req) => req,
+            PushBody::Encoded(format, body) => {
+                let reply = push_encoded(format, client, body, &logger, &handler).await;
+                return Ok(reply.into_response());
+            }
+        };
+        let (push_result, status_code) = handle_push(req, client, &logger, &handler).await;
+        Ok(warp::reply::with_status(warp::reply::json(&push_result), status_code).into_response())
+    }
+
+    /// Handles push request encoded with protobuf or msgpack. Response is
+    /// encoded in the same format as request.
+    async fn push_encoded(
+        format: BodyFormat,
+        client: Option<ClientIdentity>,
+        body: bytes::Bytes,
+        logger: &kflog::Logger,
+        handler: &ApiHandler,
+    ) -> impl Reply {
+        let (push_result, status_code) = match format.decode_request(&body) {
+            Ok(req) => handle_push(req, client, logger, handler).await,
+            Err(e) => {
+                let response = requests::PushResponse {
+                    status: String::from("err"),
+                    errors: vec![requests::PushResponseError::failed(
+                        requests::ErrorCode::InvalidRequest,
+                        e,
+                    )],
+                };
+                (response, warp::http::StatusCode::BAD_REQUEST)
+            }
+        };
+
+        warp::reply::with_header(
+            warp::reply::with_status(format.encode_response(&push_result), status_code),
+            warp::http::header::CONTENT_TYPE,
+            format.content_type(),
+        )
+    }
+
+    async fn handle_push(
+        req: requests::PushRequest,
+        client: Option<ClientIdentity>,
+        logger: &kflog::Logger,
+        handler: &ApiHandler,
+    ) -> (requests::PushResponse, warp::http::StatusCode) {
         let request_id = generate_request_id();
         let start = SystemTime::now();
 
@@ -67,7 +271,7 @@ mod handler {
         // let request_id_cloned = request_id.clone();
         let is_sync_request = req.wait_for_send;
 
-        let push_result = handler.handle_push(req).await;
+        let push_result = handler.handle_push(req, client).await;
 
         let passed_result = SystemTime::now().duration_since(start);
 
@@ -83,13 +287,8 @@ mod handler {
             Ok(psd) => (psd.as_micros() as f64) / 1000.0,
         };
 
-        let json = warp::reply::json(&push_result);
-
-        let mut status_code = warp::http::StatusCode::OK;
-        if push_result.status != "ok" {
-            status_code = warp::http::StatusCode::INTERNAL_SERVER_ERROR;
-        }
-        let result = Ok(warp::reply::with_status(json, status_code));
+        let status_code = warp::http::StatusCode::from_u16(push_result.status_code())
+            .unwrap_or(warp::http::StatusCode::INTERNAL_SERVER_ERROR);
 
         let mut method = "push/async";
         if is_sync_request.is_some() && is_sync_request.unwrap() {
@@ -115,6 +314,223 @@ mod handler {
             "request_id" => request_id,
             "passed" => (passed).to_string() + "ms",
         );
-        return result;
+        return (push_result, status_code);
+    }
+
+    /// Converts authentication failures into `UNAUTHORIZED` and malformed
+    /// request body rejections into `INVALID_REQUEST` push responses. Other
+    /// rejections are passed through.
+    pub async fn handle_rejection(err: Rejection) -> Result<warp::reply::Response, Rejection> {
+        if let Some(auth_err) = err.find::<AuthError>() {
+            let response = requests::PushResponse {
+                status: String::from("err"),
+                errors: vec![requests::PushResponseError::failed(
+                    requests::ErrorCode::Unauthorized,
+                    auth_err.message().to_string(),
+                )],
+            };
+            let reply = warp::reply::with_status(
+                warp::reply::json(&response),
+                warp::http::StatusCode::UNAUTHORIZED,
+            );
+            return Ok(warp::reply::with_header(
+                reply,
+                warp::http::header::WWW_AUTHENTICATE,
+                "Bearer",
+            )
+            .into_response());
+        }
+
+        let body_err = match err.find::<BodyDeserializeError>() {
+            None => return Err(err),
+            Some(e) => e,
+        };
+
+        let response = requests::PushResponse {
+            status: String::from("err"),
+            errors: vec![requests::PushResponseError::failed(
+                requests::ErrorCode::InvalidRequest,
+                body_err.to_string(),
+            )],
+        };
+        Ok(warp::reply::with_status(
+            warp::reply::json(&response),
+            warp::http::StatusCode::BAD_REQUEST,
+        )
+        .into_response())
+    }
+
+    /// Handles Confluent REST Proxy v2 produce request. Records are always
+    /// produced synchronously.
+    pub async fn rest_proxy_produce(
+        path: (String, Option<i32>),
+        format: rest_proxy::EmbeddedFormat,
+        client: Option<ClientIdentity>,
+        body: bytes::Bytes,
+        logger: kflog::Logger,
+        handler: Arc<ApiHandler>,
+    ) -> Result<warp::reply::Response, Infallible> {
+        let (topic, partition) = path;
+        let start = SystemTime::now();
+
+        // NOTE: warp::body::json() accepts only 'application/json' content
+        // type, so body is decoded manually.
+        let push_req = serde_json::from_slice::<rest_proxy::ProduceRequest>(&body)
+            .map_err(|e| e.to_string())
+            .and_then(|req| req.into_push_request(&topic, partition, format));
+        let push_req = match push_req {
+            Ok(r) => r,
+            Err(e) => {
+                let status_code = warp::http::StatusCode::UNPROCESSABLE_ENTITY;
+                let response = rest_proxy::ErrorResponse {
+                    error_code: rest_proxy::ERROR_CODE_UNPROCESSABLE,
+                    message: e,
+                };
+                return Ok(warp::reply::with_header(
+                    warp::reply::with_status(warp::reply::json(&response), status_code),
+                    "content-type",
+                    rest_proxy::CONTENT_TYPE_V2,
+                )
+                .into_response());
+            }
+        };
+
+        let push_result = handler.handle_push(push_req, client).await;
+        let passed = SystemTime::now()
+            .duration_since(start)
+            .map(|psd| (psd.as_micros() as f64) / 1000.0)
+            .unwrap_or_default();
+
+        let status_code = warp::http::StatusCode::OK;
+        REQUEST_DURATION
+            .with_label_values(&[&status_code.as_u16().to_string(), "rest_proxy"])
+            .observe(passed);
+
+        slog::debug!(
+            logger,
+            "proceeded_rest_proxy_request";
+            "topic" => topic,
+            "passed" => (passed).to_string() + "ms",
+        );
+
+        let response = rest_proxy::ProduceResponse::from(push_result);
+        Ok(warp::reply::with_header(
+            warp::reply::with_status(warp::reply::json(&response), status_code),
+            "content-type",
+            rest_proxy::CONTENT_TYPE_V2,
+        )
+        .into_response())
+    }
+
+    /// Handles push of a single record which payload is the request body.
+    /// Key is taken from `key` query parameter or `X-Kafka-Key` header, kafka
+    /// headers are taken from `X-Kafka-Header-*` request headers.
+    pub async fn push_raw(
+        path: (String, Option<i32>),
+        client: Option<ClientIdentity>,
+        query: RawQuery,
+        http_headers: HeaderMap,
+        body: bytes::Bytes,
+        logger: kflog::Logger,
+        handler: Arc<ApiHandler>,
+    ) -> Result<impl Reply, Infallible> {
+        let (topic, partition) = path;
+        let start = SystemTime::now();
+
+        let key = query.key.map(String::into_bytes).or_else(|| {
+            http_headers
+                .get(HEADER_KAFKA_KEY)
+                .map(|v| v.as_bytes().to_vec())
+        });
+        let headers = http_headers
+            .iter()
+            .filter_map(|(name, value)| {
+                name.as_str()
+                    .strip_prefix(HEADER_KAFKA_HEADER_PREFIX)
+                    .map(|k| (k.to_string(), value.as_bytes().to_vec()))
+            })
+            .collect();
+
+        let is_sync_request = query.wait_for_send.unwrap_or_default();
+        let record = requests::RawRecord {
+            topic,
+            partition,
+            payload: body,
+            key,
+            headers,
+            wait_for_send: is_sync_request,
+            timeout_ms: query.timeout_ms,
+        };
+        let push_result = handler.handle_raw(record, client).await;
+
+        let passed = SystemTime::now()
+            .duration_since(start)
+            .map(|psd| (psd.as_micros() as f64) / 1000.0)
+            .unwrap_or_default();
+
+        let status_code = warp::http::StatusCode::from_u16(push_result.status_code())
+            .unwrap_or(warp::http::StatusCode::INTERNAL_SERVER_ERROR);
+        let method = if is_sync_request {
+            "topics/sync"
+        } else {
+            "topics/async"
+        };
+        REQUEST_DURATION
+            .with_label_values(&[&status_code.as_u16().to_string(), method])
+            .observe(passed);
+
+        slog::debug!(
+            logger,
+            "proceeded_raw_request";
+            "passed" => (passed).to_string() + "ms",
+        );
+        Ok(warp::reply::with_status(
+            warp::reply::json(&push_result),
+            status_code,
+        ))
+    }
+
+    /// Handles bulk push of newline-delimited JSON records. Records are
+    /// produced while the body is being read; the response contains only
+    /// the numbers of lines which were not delivered.
+    pub async fn push_ndjson<S, B>(
+        client: Option<ClientIdentity>,
+        query: NdjsonQuery,
+        body: S,
+        logger: kflog::Logger,
+        handler: Arc<ApiHandler>,
+    ) -> Result<impl Reply, Infallible>
+    where
+        S: Stream<Item = Result<B, warp::Error>> + Send + 'static,
+        B: Buf + Send,
+    {
+        let start = SystemTime::now();
+
+        let push_result = handler
+            .handle_ndjson(Box::pin(body), query.timeout_ms, client)
+            .await;
+
+        let passed = SystemTime::now()
+            .duration_since(start)
+            .map(|psd| (psd.as_micros() as f64) / 1000.0)
+            .unwrap_or_default();
+
+        let status_code = warp::http::StatusCode::from_u16(push_result.status_code())
+            .unwrap_or(warp::http::StatusCode::INTERNAL_SERVER_ERROR);
+        REQUEST_DURATION
+            .with_label_values(&[&status_code.as_u16().to_string(), "push/ndjson"])
+            .observe(passed);
+
+        slog::debug!(
+            logger,
+            "proceeded_ndjson_request";
+            "accepted" => push_result.accepted,
+            "failed" => push_result.failed,
+            "passed" => (passed).to_string() + "ms",
+        );
+        Ok(warp::reply::with_status(
+            warp::reply::json(&push_result),
+            status_code,
+        ))
     }
 }
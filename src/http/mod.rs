@@ -1,3 +1,5 @@
-mod api_handler;
+pub mod api_handler;
+pub mod auth;
 mod handlers;
 pub mod server;
+pub mod tls;
@@ -1,18 +1,36 @@
+use crate::config::{TlsConfig, UnixSocketConfig};
 use crate::http::api_handler::api::ApiHandler;
-use crate::http::handlers;
-use crate::kafka::kafka;
+use crate::http::auth::Authenticator;
+use crate::http::{handlers, tls};
 use crate::log::kflog;
+use futures::FutureExt;
+use std::net::SocketAddr;
+use std::os::unix::fs::{FileTypeExt, PermissionsExt};
 use std::sync::Arc;
+use tokio::net::{TcpListener, UnixListener};
 use tokio::sync::oneshot;
 use tokio::sync::oneshot::Receiver;
+use tokio_stream::wrappers::UnixListenerStream;
 
 pub struct Config {
-    port: u16,
+    // Empty list disables TCP listeners.
+    addrs: Vec<SocketAddr>,
+    unix_socket: Option<UnixSocketConfig>,
+    // TLS settings of TCP listeners.
+    tls: Option<TlsConfig>,
 }
 
 impl Config {
-    pub fn new(port: u16) -> Config {
-        return Config { port };
+    pub fn new(
+        addrs: Vec<SocketAddr>,
+        unix_socket: Option<UnixSocketConfig>,
+        tls: Option<TlsConfig>,
+    ) -> Config {
+        return Config {
+            addrs,
+            unix_socket,
+            tls,
+        };
     }
 }
 
@@ -20,6 +38,47 @@ pub struct Server {
     config: Config,
 }
 
+/// Binds Unix socket accessible only by its owner. Umask is shared by the
+/// whole process, so it is restored right after bind.
+fn bind_owner_only(path: &str) -> std::io::Result<UnixListener> {
+    let umask = unsafe { libc::umask(0o177) };
+    let result = UnixListener::bind(path);
+    unsafe { libc::umask(umask) };
+    result
+}
+
+/// Binds Unix socket and applies its mode and owner. Stale socket left by
+/// previous run is removed, but other files are never overwritten.
+fn bind_unix_socket(config: &UnixSocketConfig) -> Result<UnixListener, String> {
+    let path = config.path();
+    if let Ok(metadata) = std::fs::symlink_metadata(path) {
+        if !metadata.file_type().is_socket() {
+            return Err(format!("'{}' exists and is not a socket", path));
+        }
+        std::fs::remove_file(path).map_err(|e| e.to_string())?;
+    }
+
+    let mode = config.mode()?;
+    let chown = config.owner().is_some() || config.group().is_some();
+    // NOTE: socket with configured mode or owner is created owner-only, so
+    // that it is never accessible by others before they are applied.
+    let listener = if mode.is_some() || chown {
+        bind_owner_only(path)
+    } else {
+        UnixListener::bind(path)
+    }
+    .map_err(|e| e.to_string())?;
+    if chown {
+        std::os::unix::fs::chown(path, config.owner(), config.group())
+            .map_err(|e| format!("failed to change owner of '{}': {}", path, e))?;
+    }
+    if let Some(mode) = mode {
+        std::fs::set_permissions(path, std::fs::Permissions::from_mode(mode))
+            .map_err(|e| format!("failed to set mode of '{}': {}", path, e))?;
+    }
+    Ok(listener)
+}
+
 impl Server {
     pub fn new_from_config(config: Config) -> Server {
         return Server { config };
@@ -28,39 +87,103 @@ impl Server {
     pub fn start_server(
         &mut self,
         logger: kflog::Logger,
-        kafka_producer: Arc<kafka::producer::Producer>,
-        ratelimiter: Arc<ratelimit::Limiter>,
+        api_handler: Arc<ApiHandler>,
+        authenticator: Arc<Authenticator>,
         shutdown_rx: Receiver<String>,
     ) -> Receiver<i8> {
+        if self.config.addrs.is_empty() && self.config.unix_socket.is_none() {
+            panic!("http server has neither tcp address nor unix socket to listen on");
+        }
+
         let logger_cloned = logger.clone();
-        let api_handler =
-            ApiHandler::new(logger_cloned.clone(), kafka_producer.clone(), ratelimiter);
-        let routes = handlers::filter::new_api(logger.clone(), api_handler);
+        let routes = handlers::filter::new_api(logger.clone(), api_handler, authenticator);
 
         let (shutdown_completed_tx, shutdown_completed_rx) = oneshot::channel::<i8>();
 
-        slog::info!(
-            logger,
-            "starting main http server";
-            "port" => self.config.port,
-        );
-
-        let (_, server) = warp::serve(routes).bind_with_graceful_shutdown(
-            ([0, 0, 0, 0], self.config.port),
-            async move {
-                shutdown_rx.await.ok();
-                slog::info!(logger_cloned, "shutting down http-server");
-                let send_result = shutdown_completed_tx.send(0);
-                if send_result.is_err() {
-                    panic!(
-                        "failed to send data to main_server shutdown channel: {}",
-                        send_result.err().unwrap()
+        // NOTE: the same shutdown signal is shared between all listeners.
+        let shutdown = async move {
+            shutdown_rx.await.ok();
+            slog::info!(logger_cloned, "shutting down http-server");
+        }
+        .shared();
+
+        let mut servers = vec![];
+        let tls_acceptor = self.config.tls.as_ref().map(|tls| {
+            tls::new_acceptor(tls)
+                .unwrap_or_else(|e| panic!("failed to initialize tls of http server: {}", e))
+        });
+        for addr in &self.config.addrs {
+            slog::info!(
+                logger,
+                "starting main http server";
+                "addr" => addr.to_string(),
+                "tls" => tls_acceptor.is_some(),
+            );
+
+            if let Some(acceptor) = &tls_acceptor {
+                let listener = std::net::TcpListener::bind(addr)
+                    .and_then(|l| l.set_nonblocking(true).map(|_| l))
+                    .and_then(TcpListener::from_std)
+                    .unwrap_or_else(|e| panic!("failed to bind http server to '{}': {}", addr, e));
+                let server = tls::serve(
+                    listener,
+                    acceptor.clone(),
+                    warp::service(routes.clone()),
+                    shutdown.clone(),
+                    logger.clone(),
+                );
+                servers.push(tokio::task::spawn(async move {
+                    server.await.ok();
+                }));
+                continue;
+            }
+
+            let (_, server) = match warp::serve(routes.clone())
+                .try_bind_with_graceful_shutdown(*addr, shutdown.clone())
+            {
+                Ok(server) => server,
+                Err(e) => panic!("failed to bind http server to '{}': {}", addr, e),
+            };
+            servers.push(tokio::task::spawn(server));
+        }
+
+        let socket_path = self
+            .config
+            .unix_socket
+            .as_ref()
+            .map(|s| s.path().to_string());
+        if let Some(socket) = &self.config.unix_socket {
+            slog::info!(
+                logger,
+                "starting main http server";
+                "unix_socket" => socket.path(),
+            );
+
+            let listener = match bind_unix_socket(socket) {
+                Ok(listener) => listener,
+                Err(e) => panic!("failed to bind unix socket '{}': {}", socket.path(), e),
+            };
+            let server = warp::serve(routes)
+                .serve_incoming_with_graceful_shutdown(UnixListenerStream::new(listener), shutdown);
+            servers.push(tokio::task::spawn(server));
+        }
+
+        tokio::task::spawn(async move {
+            for server in servers {
+                server.await.ok();
+            }
+            if let Some(path) = socket_path {
+                if let Err(e) = std::fs::remove_file(&path) {
+                    slog::warn!(
+                        logger,
+                        "failed to remove unix socket";
+                        "unix_socket" => path,
+                        "error" => e.to_string(),
                     );
                 }
-            },
-        );
-        let task = tokio::task::spawn(server);
-        tokio::task::spawn(async move { task.await });
+            }
+            shutdown_completed_tx.send(0).ok();
+        });
         return shutdown_completed_rx;
     }
 }
@@ -0,0 +1,244 @@
+use crate::config::TlsConfig;
+use crate::log::kflog;
+use futures::{Future, Stream, StreamExt};
+use hyper::service::{make_service_fn, service_fn, Service};
+use hyper::{Body, Request, Response};
+use std::convert::Infallible;
+use std::io::BufReader;
+use std::pin::Pin;
+use std::sync::Arc;
+use std::task::{Context, Poll};
+use std::time::Duration;
+use tokio::io::{AsyncRead, AsyncWrite, ReadBuf};
+use tokio::net::{TcpListener, TcpStream};
+use tokio_rustls::rustls::server::{
+    AllowAnyAnonymousOrAuthenticatedClient, AllowAnyAuthenticatedClient, ServerConnection,
+};
+use tokio_rustls::rustls::{Certificate, PrivateKey, RootCertStore, ServerConfig};
+use tokio_rustls::server::TlsStream;
+use tokio_rustls::TlsAcceptor;
+use tokio_stream::wrappers::TcpListenerStream;
+
+const HANDSHAKE_TIMEOUT: Duration = Duration::from_secs(10);
+
+const MAX_PENDING_HANDSHAKES: usize = 128;
+
+/// ClientCert describes verified certificate of the client. It is passed
+/// to request handlers as request extension.
+#[derive(Debug, Clone)]
+pub struct ClientCert {
+    /// Subject of the certificate, e.g. "O=city-mobil, CN=php-backend".
+    pub subject: String,
+}
+
+impl ClientCert {
+    fn from_connection(conn: &ServerConnection) -> Option<ClientCert> {
+        let cert = conn.peer_certificates()?.first()?;
+        ClientCert::parse(&cert.0)
+    }
+
+    fn parse(der: &[u8]) -> Option<ClientCert> {
+        let (_, cert) = x509_parser::parse_x509_certificate(der).ok()?;
+        Some(ClientCert {
+            subject: cert.subject().to_string(),
+        })
+    }
+}
+
+fn load_certs(path: &str) -> Result<Vec<Certificate>, String> {
+    let file = std::fs::File::open(path).map_err(|e| format!("'{}': {}", path, e))?;
+    let certs = rustls_pemfile::certs(&mut BufReader::new(file))
+        .map_err(|e| format!("'{}': {}", path, e))?;
+    if certs.is_empty() {
+        return Err(format!("'{}': no certificates found", path));
+    }
+    Ok(certs.into_iter().map(Certificate).collect())
+}
+
+fn load_key(path: &str) -> Result<PrivateKey, String> {
+    let file = std::fs::File::open(path).map_err(|e| format!("'{}': {}", path, e))?;
+    let items = rustls_pemfile::read_all(&mut BufReader::new(file))
+        .map_err(|e| format!("'{}': {}", path, e))?;
+    items
+        .into_iter()
+        .find_map(|item| match item {
+            rustls_pemfile::Item::RSAKey(key)
+            | rustls_pemfile::Item::PKCS8Key(key)
+            | rustls_pemfile::Item::ECKey(key) => Some(PrivateKey(key)),
+            _ => None,
+        })
+        .ok_or_else(|| format!("'{}': no private key found", path))
+}
+
+/// Creates TLS acceptor from certificates described in config.
+pub fn new_acceptor(config: &TlsConfig) -> Result<TlsAcceptor, String> {
+    let certs = load_certs(config.cert_path())?;
+    let key = load_key(config.key_path())?;
+
+    let builder = ServerConfig::builder().with_safe_defaults();
+    let builder = match config.client_ca_path() {
+        None => builder.with_no_client_auth(),
+        Some(path) => {
+            let mut roots = RootCertStore::empty();
+            for cert in load_certs(path)? {
+                roots
+                    .add(&cert)
+                    .map_err(|e| format!("'{}': invalid CA certificate: {}", path, e))?;
+            }
+            let verifier = if config.client_auth_required() {
+                AllowAnyAuthenticatedClient::new(roots)
+            } else {
+                AllowAnyAnonymousOrAuthenticatedClient::new(roots)
+            };
+            builder.with_client_cert_verifier(verifier)
+        }
+    };
+
+    let mut server_config = builder
+        .with_single_cert(certs, key)
+        .map_err(|e| e.to_string())?;
+    server_config.alpn_protocols = vec![b"h2".to_vec(), b"http/1.1".to_vec()];
+    Ok(TlsAcceptor::from(Arc::new(server_config)))
+}
+
+async fn handshake(
+    conn: std::io::Result<TcpStream>,
+    acceptor: TlsAcceptor,
+    logger: kflog::Logger,
+) -> Option<TlsStream<TcpStream>> {
+    let stream = match conn {
+        Ok(stream) => stream,
+        Err(e) => {
+            slog::warn!(
+                logger,
+                "failed to accept connection";
+                "error" => e.to_string(),
+            );
+            return None;
+        }
+    };
+
+    let err = match tokio::time::timeout(HANDSHAKE_TIMEOUT, acceptor.accept(stream)).await {
+        Ok(Ok(stream)) => return Some(stream),
+        Ok(Err(e)) => e.to_string(),
+        Err(_) => String::from("handshake timed out"),
+    };
+    slog::warn!(
+        logger,
+        "tls handshake failed";
+        "error" => err,
+    );
+    None
+}
+
+/// Returns TLS connections accepted by listener.
+pub fn incoming(
+    listener: TcpListener,
+    acceptor: TlsAcceptor,
+    logger: kflog::Logger,
+) -> impl Stream<Item = Result<TlsStream<TcpStream>, Infallible>> {
+    // NOTE: handshakes are made concurrently, so that slow clients do not
+    // block accepting of other connections.
+    TcpListenerStream::new(listener)
+        .map(move |conn| handshake(conn, acceptor.clone(), logger.clone()))
+        .buffer_unordered(MAX_PENDING_HANDSHAKES)
+        .filter_map(|stream| async move { stream.map(Ok::<_, Infallible>) })
+}
+
+/// TlsConnection is a TLS connection of gRPC server. Certificate of the
+/// client, if any, is available as `Option<ClientCert>` in extensions of
+/// every request of the connection.
+pub struct TlsConnection(pub TlsStream<TcpStream>);
+
+impl tonic::transport::server::Connected for TlsConnection {
+    type ConnectInfo = Option<ClientCert>;
+
+    fn connect_info(&self) -> Self::ConnectInfo {
+        ClientCert::from_connection(self.0.get_ref().1)
+    }
+}
+
+impl AsyncRead for TlsConnection {
+    fn poll_read(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &mut ReadBuf<'_>,
+    ) -> Poll<std::io::Result<()>> {
+        Pin::new(&mut self.get_mut().0).poll_read(cx, buf)
+    }
+}
+
+impl AsyncWrite for TlsConnection {
+    fn poll_write(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &[u8],
+    ) -> Poll<std::io::Result<usize>> {
+        Pin::new(&mut self.get_mut().0).poll_write(cx, buf)
+    }
+
+    fn poll_flush(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<std::io::Result<()>> {
+        Pin::new(&mut self.get_mut().0).poll_flush(cx)
+    }
+
+    fn poll_shutdown(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<std::io::Result<()>> {
+        Pin::new(&mut self.get_mut().0).poll_shutdown(cx)
+    }
+}
+
+/// Serves HTTPS connections accepted by listener with the given service.
+/// Certificate of the client, if any, is inserted into extensions of
+/// every request of the connection.
+pub fn serve<S, F>(
+    listener: TcpListener,
+    acceptor: TlsAcceptor,
+    service: S,
+    shutdown: F,
+    logger: kflog::Logger,
+) -> impl Future<Output = hyper::Result<()>>
+where
+    S: Service<Request<Body>, Response = Response<Body>, Error = Infallible>
+        + Clone
+        + Send
+        + 'static,
+    S::Future: Send + 'static,
+    F: Future<Output = ()>,
+{
+    let incoming = incoming(listener, acceptor, logger);
+    let make_service = make_service_fn(move |stream: &TlsStream<TcpStream>| {
+        let client_cert = ClientCert::from_connection(stream.get_ref().1);
+        let service = service.clone();
+        async move {
+            Ok::<_, Infallible>(service_fn(move |mut req: Request<Body>| {
+                if let Some(cert) = &client_cert {
+                    req.extensions_mut().insert(cert.clone());
+                }
+                service.clone().call(req)
+            }))
+        }
+    });
+
+    hyper::Server::builder(hyper::server::accept::from_stream(incoming))
+        .serve(make_service)
+        .with_graceful_shutdown(shutdown)
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::http::tls::{load_certs, load_key, ClientCert};
+
+    #[test]
+    fn test_load_certs() {
+        assert_eq!(load_certs("testdata/tls/server.pem").unwrap().len(), 1);
+        assert!(load_key("testdata/tls/server.key").is_ok());
+        assert!(load_certs("testdata/tls/server.key").is_err());
+        assert!(load_key("testdata/tls/server.pem").is_err());
+    }
+
+    #[test]
+    fn test_client_cert_parse() {
+        let cert = &load_certs("testdata/tls/client.pem").unwrap()[0];
+        let client_cert = ClientCert::parse(&cert.0).unwrap();
+        assert_eq!(client_cert.subject, "O=city-mobil, CN=php-backend");
+    }
+}
@@ -0,0 +1,642 @@
+use crate::config::{FailoverConfig, MirrorConfig};
+use crate::kafka::kafka::config::KafkaConfig;
+use crate::kafka::kafka::producer;
+use crate::log::kflog;
+use rdkafka::error::KafkaError;
+use rdkafka::message::OwnedHeaders;
+use serde::Deserialize;
+use std::collections::{BTreeMap, HashMap};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+/// Name of the cluster configured by `kafka` section.
+pub const DEFAULT_CLUSTER: &str = "default";
+
+/// How often availability of clusters with failover is checked.
+const FAILOVER_CHECK_INTERVAL: Duration = Duration::from_millis(1000);
+
+/// Statistics interval of clusters with failover if statistics are disabled.
+const FAILOVER_STATISTICS_INTERVAL_MS: u32 = 1000;
+
+lazy_static::lazy_static!(
+    static ref MIRROR_SENT_MESSAGES_COUNT: prometheus::IntCounterVec =
+        prometheus::register_int_counter_vec!(
+            "kafka_mirror_sent_messages",
+            "Kafka messages sent to secondary cluster count",
+            &["cluster", "topic"]
+        )
+        .unwrap();
+    static ref MIRROR_ERRORS_COUNT: prometheus::IntCounterVec =
+        prometheus::register_int_counter_vec!(
+            "kafka_mirror_errors_count",
+            "Kafka errors of secondary cluster count",
+            &["cluster", "topic", "error_code"]
+        )
+        .unwrap();
+    static ref FAILOVER_SWITCHES_COUNT: prometheus::IntCounterVec =
+        prometheus::register_int_counter_vec!(
+            "kafka_failover_switches_count",
+            "Switches of cluster between primary and standby count",
+            &["cluster", "to"]
+        )
+        .unwrap();
+    static ref FAILOVER_STANDBY_ACTIVE: prometheus::IntGaugeVec =
+        prometheus::register_int_gauge_vec!(
+            "kafka_failover_standby_active",
+            "Whether records of cluster are produced to standby",
+            &["cluster"]
+        )
+        .unwrap();
+);
+
+/// MirrorResponse describes which outcome decides the response of mirrored
+/// records.
+#[derive(Debug, Clone, Copy, PartialEq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum MirrorResponse {
+    /// Record is delivered if it is delivered to primary cluster. Secondary
+    /// cluster is written in background.
+    Primary,
+    /// Record is delivered if it is delivered to both clusters.
+    Both,
+    /// Record is delivered if it is delivered to any of clusters.
+    Either,
+}
+
+impl Default for MirrorResponse {
+    fn default() -> Self {
+        MirrorResponse::Primary
+    }
+}
+
+struct Mirror {
+    cluster: String,
+    producer: Arc<producer::Producer>,
+    response: MirrorResponse,
+}
+
+impl Mirror {
+    fn record_result(&self, topic: &str, result: &Result<(), KafkaError>) {
+        MIRROR_SENT_MESSAGES_COUNT
+            .with_label_values(&[&self.cluster, topic])
+            .inc();
+        if let Err(err) = result {
+            let code = err.rdkafka_error_code().map(|c| c as i32).unwrap_or(-1);
+            MIRROR_ERRORS_COUNT
+                .with_label_values(&[&self.cluster, topic, &code.to_string()])
+                .inc();
+        }
+    }
+
+    /// Produces messages to the secondary cluster in a single transaction.
+    async fn send_transaction(
+        &self,
+        messages: &[OwnedMessage],
+        timeout: Duration,
+    ) -> Option<producer::TransactionOutcome> {
+        let outcome = self
+            .producer
+            .send_transaction(messages.iter().map(|m| m.message()).collect(), timeout)
+            .await;
+        let result = match &outcome {
+            // NOTE: transactions of secondary cluster are checked on start.
+            None => Err(KafkaError::Canceled),
+            Some(outcome) => transaction_result(outcome),
+        };
+        for message in messages.iter() {
+            self.record_result(&message.topic, &result);
+        }
+        outcome
+    }
+}
+
+/// Failover is a standby cluster records are produced to while the primary
+/// one is unavailable.
+struct Failover {
+    cluster: String,
+    standby_cluster: String,
+    standby: Arc<producer::Producer>,
+    active: AtomicBool,
+    failover_after: Duration,
+    failback_after: Duration,
+}
+
+impl Failover {
+    fn switch(&self, logger: &kflog::Logger, active: bool, elapsed: Duration) {
+        self.active.store(active, Ordering::SeqCst);
+        FAILOVER_STANDBY_ACTIVE
+            .with_label_values(&[&self.cluster])
+            .set(active as i64);
+        if active {
+            FAILOVER_SWITCHES_COUNT
+                .with_label_values(&[&self.cluster, &self.standby_cluster])
+                .inc();
+            slog::warn!(
+                logger,
+                "all brokers are down, switching to standby cluster";
+                "cluster" => &self.cluster,
+                "standby" => &self.standby_cluster,
+                "down_ms" => elapsed.as_millis() as u64,
+            );
+        } else {
+            FAILOVER_SWITCHES_COUNT
+                .with_label_values(&[&self.cluster, &self.cluster])
+                .inc();
+            slog::info!(
+                logger,
+                "brokers are up, switching back from standby cluster";
+                "cluster" => &self.cluster,
+                "standby" => &self.standby_cluster,
+                "up_ms" => elapsed.as_millis() as u64,
+            );
+        }
+    }
+}
+
+/// OwnedMessage is a copy of message sent to secondary cluster in
+/// background.
+struct OwnedMessage {
+    topic: String,
+    payload: Option<Vec<u8>>,
+    key: Option<Vec<u8>>,
+    partition: Option<i32>,
+    headers: Option<OwnedHeaders>,
+    timestamp: Option<i64>,
+}
+
+impl OwnedMessage {
+    fn new(message: producer::Message<'_>) -> OwnedMessage {
+        OwnedMessage {
+            topic: message.topic.to_string(),
+            payload: message.payload.map(|p| p.to_vec()),
+            key: message.key.map(|k| k.to_vec()),
+            partition: message.partition,
+            headers: message.headers,
+            timestamp: message.timestamp,
+        }
+    }
+
+    fn message(&self) -> producer::Message<'_> {
+        producer::Message {
+            topic: &self.topic,
+            payload: self.payload.as_deref(),
+            key: self.key.as_deref(),
+            partition: self.partition,
+            headers: self.headers.clone(),
+            timestamp: self.timestamp,
+        }
+    }
+}
+
+/// Returns error which aborted the transaction.
+fn transaction_result(outcome: &producer::TransactionOutcome) -> Result<(), KafkaError> {
+    if outcome.committed() {
+        return Ok(());
+    }
+    if let Some(err) = &outcome.error {
+        return Err(err.clone());
+    }
+    outcome
+        .results
+        .iter()
+        .try_for_each(|result| result.as_ref().map(|_| ()).map_err(|(err, _)| err.clone()))
+}
+
+/// Cluster is a kafka cluster records are produced to, together with its
+/// mirror and standby.
+pub struct Cluster {
+    producer: Arc<producer::Producer>,
+    mirror: Option<Arc<Mirror>>,
+    failover: Option<Arc<Failover>>,
+}
+
+impl Cluster {
+    /// Returns producer of standby cluster if the cluster is failed over,
+    /// otherwise producer of the cluster itself.
+    fn active_producer(&self) -> &Arc<producer::Producer> {
+        match &self.failover {
+            Some(failover) if failover.active.load(Ordering::SeqCst) => &failover.standby,
+            _ => &self.producer,
+        }
+    }
+
+    /// Checks availability of the cluster until the application is stopped
+    /// and switches records to standby cluster and back.
+    async fn monitor_failover(&self, logger: kflog::Logger, failover: Arc<Failover>) {
+        FAILOVER_STANDBY_ACTIVE
+            .with_label_values(&[&failover.cluster])
+            .set(0);
+        let mut interval = tokio::time::interval(FAILOVER_CHECK_INTERVAL);
+        let mut since: Option<(bool, Instant)> = None;
+        // Set while the cluster should fail over, but its standby is down.
+        let mut standby_down = false;
+        loop {
+            interval.tick().await;
+            // NOTE: availability is unknown until the first statistics.
+            let available = match self.producer.available() {
+                None => continue,
+                Some(available) => available,
+            };
+            let changed_at = match since {
+                Some((was_available, changed_at)) if was_available == available => changed_at,
+                _ => {
+                    since = Some((available, Instant::now()));
+                    continue;
+                }
+            };
+            let active = failover.active.load(Ordering::SeqCst);
+            let elapsed = changed_at.elapsed();
+            if !active && !available && elapsed >= failover.failover_after {
+                // NOTE: records are not switched to standby cluster, which
+                // is unavailable too.
+                if failover.standby.available() != Some(true) {
+                    if !standby_down {
+                        slog::error!(
+                            logger,
+                            "all brokers are down, but standby cluster is unavailable";
+                            "cluster" => &failover.cluster,
+                            "standby" => &failover.standby_cluster,
+                        );
+                        standby_down = true;
+                    }
+                    continue;
+                }
+                standby_down = false;
+                failover.switch(&logger, true, elapsed);
+            } else if active && available && elapsed >= failover.failback_after {
+                failover.switch(&logger, false, elapsed);
+            }
+        }
+    }
+
+    /// Sends message to the cluster and to its mirror, if it is configured.
+    /// Delivery result is decided by `MirrorResponse` of the mirror.
+    pub async fn send(
+        &self,
+        message: producer::Message<'_>,
+        timeout: Duration,
+    ) -> producer::OwnedDeliveryResult {
+        let mirror = match &self.mirror {
+            None => return self.active_producer().send(message, timeout).await,
+            Some(mirror) => mirror,
+        };
+
+        if mirror.response == MirrorResponse::Primary {
+            let owned = OwnedMessage::new(message.clone());
+            let mirror = mirror.clone();
+            tokio::spawn(async move {
+                let result = mirror.producer.send(owned.message(), timeout).await;
+                mirror.record_result(&owned.topic, &result.map(|_| ()).map_err(|(e, _)| e));
+            });
+            return self.active_producer().send(message, timeout).await;
+        }
+
+        let topic = message.topic;
+        let (primary, secondary) = futures::join!(
+            self.active_producer().send(message.clone(), timeout),
+            mirror.producer.send(message, timeout)
+        );
+        mirror.record_result(
+            topic,
+            &secondary.as_ref().map(|_| ()).map_err(|(e, _)| e.clone()),
+        );
+        // NOTE: position of the primary cluster is reported, unless the
+        // record is delivered only to the secondary one.
+        match (mirror.response, primary, secondary) {
+            (MirrorResponse::Both, Ok(_), Err(err)) => Err(err),
+            (MirrorResponse::Either, Err(_), Ok(position)) => Ok(position),
+            (_, primary, _) => primary,
+        }
+    }
+
+    /// Sends messages in a single transaction. Transaction of the mirror is
+    /// started only after the primary one is finished, so that records of
+    /// aborted transaction are never committed to both clusters. Returns None
+    /// if transactions are disabled.
+    pub async fn send_transaction(
+        &self,
+        messages: Vec<producer::Message<'_>>,
+        timeout: Duration,
+    ) -> Option<producer::TransactionOutcome> {
+        let mirror = match &self.mirror {
+            None => {
+                return self
+                    .active_producer()
+                    .send_transaction(messages, timeout)
+                    .await
+            }
+            Some(mirror) => mirror.clone(),
+        };
+
+        let owned = messages
+            .iter()
+            .cloned()
+            .map(OwnedMessage::new)
+            .collect::<Vec<_>>();
+        let outcome = self
+            .active_producer()
+            .send_transaction(messages, timeout)
+            .await?;
+        match (mirror.response, outcome.committed()) {
+            // NOTE: the primary transaction is already committed, so failure
+            // of the secondary one is reported separately.
+            (MirrorResponse::Both, true) => {
+                let secondary = mirror.send_transaction(&owned, timeout).await;
+                match secondary.as_ref().map(transaction_result) {
+                    Some(Err(err)) => Some(producer::TransactionOutcome {
+                        mirror_error: Some(err),
+                        ..outcome
+                    }),
+                    _ => Some(outcome),
+                }
+            }
+            (MirrorResponse::Either, false) => {
+                match mirror.send_transaction(&owned, timeout).await {
+                    Some(secondary) if secondary.committed() => Some(secondary),
+                    _ => Some(outcome),
+                }
+            }
+            (_, true) => {
+                tokio::spawn(async move { mirror.send_transaction(&owned, timeout).await });
+                Some(outcome)
+            }
+            (_, false) => Some(outcome),
+        }
+    }
+}
+
+/// Clusters holds producers of all configured kafka clusters.
+pub struct Clusters {
+    clusters: HashMap<String, Arc<Cluster>>,
+}
+
+impl Clusters {
+    /// Validates configs of clusters, mirrors and failovers and creates
+    /// producers. Failovers are not monitored until `start_failovers` is
+    /// called.
+    pub fn new(
+        logger: &kflog::Logger,
+        default: KafkaConfig,
+        clusters: HashMap<String, KafkaConfig>,
+        mirrors: &[MirrorConfig],
+        failovers: &[FailoverConfig],
+    ) -> Result<Clusters, String> {
+        if clusters.contains_key(DEFAULT_CLUSTER) {
+            return Err(format!(
+                "cluster name '{}' is reserved for kafka section",
+                DEFAULT_CLUSTER
+            ));
+        }
+        let mut configs = clusters;
+        configs.insert(DEFAULT_CLUSTER.to_string(), default);
+        for (name, config) in configs.iter() {
+            config
+                .validate()
+                .map_err(|e| format!("cluster '{}': {}", name, e))?;
+        }
+        Clusters::validate_mirrors(&configs, mirrors)?;
+        Clusters::validate_failovers(&configs, mirrors, failovers)?;
+        // NOTE: availability of brokers is known from statistics only, both
+        // for the cluster and its standby.
+        for failover in failovers.iter() {
+            for name in [failover.cluster(), failover.standby()].iter() {
+                let config = configs.get_mut(*name).unwrap();
+                if config.statistics_interval_ms == Some(0) {
+                    slog::warn!(
+                        logger,
+                        "statistics are disabled, enabling them for failover";
+                        "cluster" => *name,
+                        "statistics_interval_ms" => FAILOVER_STATISTICS_INTERVAL_MS,
+                    );
+                    config.statistics_interval_ms = Some(FAILOVER_STATISTICS_INTERVAL_MS);
+                }
+            }
+        }
+
+        let producers = configs
+            .into_iter()
+            .map(|(name, config)| {
+                let producer = producer::new(&name, config);
+                (name, producer)
+            })
+            .collect::<HashMap<_, _>>();
+        let clusters = producers
+            .iter()
+            .map(|(name, producer)| {
+                let mirror = mirrors.iter().find(|m| m.cluster() == name).map(|m| {
+                    Arc::new(Mirror {
+                        cluster: m.secondary().to_string(),
+                        producer: producers[m.secondary()].clone(),
+                        response: m.response(),
+                    })
+                });
+                let failover = failovers.iter().find(|f| f.cluster() == name).map(|f| {
+                    Arc::new(Failover {
+                        cluster: name.clone(),
+                        standby_cluster: f.standby().to_string(),
+                        standby: producers[f.standby()].clone(),
+                        active: AtomicBool::new(false),
+                        failover_after: f.failover_after(),
+                        failback_after: f.failback_after(),
+                    })
+                });
+                let cluster = Cluster {
+                    producer: producer.clone(),
+                    mirror,
+                    failover,
+                };
+                (name.clone(), Arc::new(cluster))
+            })
+            .collect();
+        Ok(Clusters { clusters })
+    }
+
+    fn validate_mirrors(
+        configs: &HashMap<String, KafkaConfig>,
+        mirrors: &[MirrorConfig],
+    ) -> Result<(), String> {
+        for (i, mirror) in mirrors.iter().enumerate() {
+            let primary = configs
+                .get(mirror.cluster())
+                .ok_or_else(|| format!("mirror of unknown cluster '{}'", mirror.cluster()))?;
+            let secondary = configs.get(mirror.secondary()).ok_or_else(|| {
+                format!(
+                    "mirror of cluster '{}' refers to unknown cluster '{}'",
+                    mirror.cluster(),
+                    mirror.secondary()
+                )
+            })?;
+            if mirror.cluster() == mirror.secondary() {
+                return Err(format!(
+                    "cluster '{}' can not be mirrored to itself",
+                    mirror.cluster()
+                ));
+            }
+            if mirrors[..i].iter().any(|m| m.cluster() == mirror.cluster()) {
+                return Err(format!(
+                    "cluster '{}' has several mirrors",
+                    mirror.cluster()
+                ));
+            }
+            if primary.transactions.enabled && !secondary.transactions.enabled {
+                return Err(format!(
+                    "transactions must be enabled in cluster '{}' to mirror atomic pushes of cluster '{}'",
+                    mirror.secondary(),
+                    mirror.cluster()
+                ));
+            }
+        }
+        Ok(())
+    }
+
+    fn validate_failovers(
+        configs: &HashMap<String, KafkaConfig>,
+        mirrors: &[MirrorConfig],
+        failovers: &[FailoverConfig],
+    ) -> Result<(), String> {
+        for (i, failover) in failovers.iter().enumerate() {
+            let primary = configs
+                .get(failover.cluster())
+                .ok_or_else(|| format!("failover of unknown cluster '{}'", failover.cluster()))?;
+            let standby = configs.get(failover.standby()).ok_or_else(|| {
+                format!(
+                    "failover of cluster '{}' refers to unknown cluster '{}'",
+                    failover.cluster(),
+                    failover.standby()
+                )
+            })?;
+            if failover.cluster() == failover.standby() {
+                return Err(format!(
+                    "cluster '{}' can not fail over to itself",
+                    failover.cluster()
+                ));
+            }
+            if failovers[..i]
+                .iter()
+                .any(|f| f.cluster() == failover.cluster())
+            {
+                return Err(format!(
+                    "cluster '{}' has several standby clusters",
+                    failover.cluster()
+                ));
+            }
+            if primary.transactions.enabled && !standby.transactions.enabled {
+                return Err(format!(
+                    "transactions must be enabled in cluster '{}' to fail over atomic pushes of cluster '{}'",
+                    failover.standby(),
+                    failover.cluster()
+                ));
+            }
+            if mirrors
+                .iter()
+                .any(|m| m.cluster() == failover.cluster() && m.secondary() == failover.standby())
+            {
+                return Err(format!(
+                    "cluster '{}' can not fail over to its mirror '{}'",
+                    failover.cluster(),
+                    failover.standby()
+                ));
+            }
+        }
+        Ok(())
+    }
+
+    /// Starts monitoring of clusters with failover in background.
+    pub fn start_failovers(&self, logger: kflog::Logger) {
+        for cluster in self.clusters.values() {
+            if let Some(failover) = &cluster.failover {
+                let cluster = cluster.clone();
+                let failover = failover.clone();
+                let logger = logger.clone();
+                tokio::spawn(async move { cluster.monitor_failover(logger, failover).await });
+            }
+        }
+    }
+
+    /// Returns names of all clusters, including the default one.
+    pub fn names(&self) -> Vec<String> {
+        let mut names = self.clusters.keys().cloned().collect::<Vec<_>>();
+        names.sort();
+        names
+    }
+
+    pub fn get(&self, name: &str) -> Option<&Arc<Cluster>> {
+        self.clusters.get(name)
+    }
+
+    pub fn status(&self) -> BTreeMap<String, producer::Status> {
+        self.clusters
+            .iter()
+            .map(|(name, cluster)| (name.clone(), cluster.producer.status()))
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::config::{FailoverConfig, KafkaProxyConfig, MirrorConfig};
+    use crate::kafka::cluster::{Clusters, DEFAULT_CLUSTER};
+
+    #[test]
+    fn test_validate_mirrors() {
+        let config =
+            KafkaProxyConfig::initialize_config(&String::from("testdata/kafka_config.yaml"))
+                .unwrap();
+        let mut configs = config.get_clusters_config();
+        configs.insert(DEFAULT_CLUSTER.to_string(), config.get_kafka_config());
+        let mirrors = config.get_mirrors_config();
+        assert_eq!(Clusters::validate_mirrors(&configs, &mirrors), Ok(()));
+
+        let mirror = |json: &str| serde_json::from_str::<MirrorConfig>(json).unwrap();
+        let invalid = [
+            mirror(r#"{"cluster": "logs", "secondary": "default"}"#),
+            mirror(r#"{"cluster": "billing", "secondary": "logs"}"#),
+            mirror(r#"{"cluster": "billing", "secondary": "billing"}"#),
+            // transactions are enabled only in the default cluster.
+            mirror(r#"{"cluster": "default", "secondary": "billing"}"#),
+        ];
+        for mirror in invalid.iter() {
+            let mirrors = vec![mirror.clone()];
+            assert!(Clusters::validate_mirrors(&configs, &mirrors).is_err());
+        }
+
+        let mut mirrors = mirrors;
+        mirrors.push(mirrors[0].clone());
+        assert!(Clusters::validate_mirrors(&configs, &mirrors).is_err());
+    }
+
+    #[test]
+    fn test_validate_failovers() {
+        let config =
+            KafkaProxyConfig::initialize_config(&String::from("testdata/kafka_config.yaml"))
+                .unwrap();
+        let mut configs = config.get_clusters_config();
+        configs.insert(DEFAULT_CLUSTER.to_string(), config.get_kafka_config());
+        let mirrors = config.get_mirrors_config();
+        let failovers = config.get_failovers_config();
+        assert_eq!(
+            Clusters::validate_failovers(&configs, &mirrors, &failovers),
+            Ok(())
+        );
+
+        let failover = |json: &str| serde_json::from_str::<FailoverConfig>(json).unwrap();
+        let invalid = [
+            failover(r#"{"cluster": "logs", "standby": "default"}"#),
+            failover(r#"{"cluster": "billing", "standby": "logs"}"#),
+            failover(r#"{"cluster": "billing", "standby": "billing"}"#),
+            // billing is mirrored to the default cluster.
+            failover(r#"{"cluster": "billing", "standby": "default"}"#),
+            // transactions are not enabled in billing cluster.
+            failover(r#"{"cluster": "standby", "standby": "billing"}"#),
+        ];
+        for failover in invalid.iter() {
+            let failovers = vec![failover.clone()];
+            assert!(Clusters::validate_failovers(&configs, &mirrors, &failovers).is_err());
+        }
+
+        let mut failovers = failovers;
+        failovers.push(failovers[0].clone());
+        assert!(Clusters::validate_failovers(&configs, &mirrors, &failovers).is_err());
+    }
+}
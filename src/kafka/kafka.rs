@@ -11,6 +11,273 @@ pub mod config {
     const DEFAULT_REQUEST_TIMEOUT_MS: u32 = 30000;
     const DEFAULT_QUEUE_BUFFERING_MAX_KBYTES: u32 = 1048576;
     const DEFAULT_STATISTICS_INTERVAL_MS: u32 = 0;
+    // NOTE: statistics are required to report producer id of idempotent
+    // producer.
+    const IDEMPOTENT_STATISTICS_INTERVAL_MS: u32 = 1000;
+    // Maximum number of in-flight requests librdkafka allows for idempotent
+    // producer.
+    const IDEMPOTENT_MAX_IN_FLIGHT: u32 = 5;
+    const DEFAULT_TRANSACTIONS_POOL_SIZE: u32 = 4;
+    const DEFAULT_TRANSACTIONS_ID_PREFIX: &str = "kprf";
+    const DEFAULT_TRANSACTIONS_TIMEOUT_MS: u32 = 10000; // librdkafka default is 60000
+
+    /// Properties which are set by typed options and can not be overridden
+    /// with `librdkafka_overrides`, including their librdkafka aliases.
+    const MANAGED_PROPERTIES: &[&str] = &[
+        "bootstrap.servers",
+        "metadata.broker.list",
+        "sasl.username",
+        "sasl.password",
+        "security.protocol",
+        "sasl.mechanism",
+        "sasl.mechanisms",
+        "sasl.oauthbearer.config",
+        "ssl.ca.location",
+        "ssl.certificate.location",
+        "ssl.key.location",
+        "ssl.key.password",
+        "ssl.endpoint.identification.algorithm",
+        "message.max.bytes",
+        "queue.buffering.max.messages",
+        "queue.buffering.max.ms",
+        "linger.ms",
+        "queue.buffering.max.kbytes",
+        "retries",
+        "message.send.max.retries",
+        "message.timeout.ms",
+        "delivery.timeout.ms",
+        "request.timeout.ms",
+        "request.required.acks",
+        "acks",
+        "statistics.interval.ms",
+        "allow.auto.create.topics",
+        "compression.codec",
+        "compression.type",
+        "compression.level",
+        "enable.idempotence",
+        "transactional.id",
+        "transaction.timeout.ms",
+    ];
+
+    /// TransactionsConfig describes a pool of transactional producers used by
+    /// atomic pushes.
+    #[derive(Debug, Clone, Deserialize)]
+    pub struct TransactionsConfig {
+        #[serde(default)]
+        pub enabled: bool,
+
+        // Maximum number of transactions running at the same time.
+        #[serde(default = "TransactionsConfig::default_pool_size")]
+        pub pool_size: Option<u32>,
+
+        // Producers use 'transactional.id' '<id_prefix>-<n>'. Prefix must be
+        // unique for every kprf instance, otherwise instances fence each
+        // other.
+        #[serde(default = "TransactionsConfig::default_id_prefix")]
+        pub id_prefix: Option<String>,
+
+        #[serde(default = "TransactionsConfig::default_timeout_ms")]
+        pub timeout_ms: Option<u32>,
+    }
+
+    impl Default for TransactionsConfig {
+        fn default() -> Self {
+            TransactionsConfig {
+                enabled: false,
+                pool_size: TransactionsConfig::default_pool_size(),
+                id_prefix: TransactionsConfig::default_id_prefix(),
+                timeout_ms: TransactionsConfig::default_timeout_ms(),
+            }
+        }
+    }
+
+    impl TransactionsConfig {
+        fn default_pool_size() -> Option<u32> {
+            Some(DEFAULT_TRANSACTIONS_POOL_SIZE)
+        }
+
+        fn default_id_prefix() -> Option<String> {
+            Some(DEFAULT_TRANSACTIONS_ID_PREFIX.to_string())
+        }
+
+        fn default_timeout_ms() -> Option<u32> {
+            Some(DEFAULT_TRANSACTIONS_TIMEOUT_MS)
+        }
+
+        pub fn pool_size(&self) -> u32 {
+            self.pool_size.unwrap_or(DEFAULT_TRANSACTIONS_POOL_SIZE)
+        }
+
+        pub fn id_prefix(&self) -> String {
+            self.id_prefix
+                .clone()
+                .unwrap_or_else(|| DEFAULT_TRANSACTIONS_ID_PREFIX.to_string())
+        }
+
+        pub fn timeout_ms(&self) -> u32 {
+            self.timeout_ms.unwrap_or(DEFAULT_TRANSACTIONS_TIMEOUT_MS)
+        }
+
+        fn validate(&self, message_timeout_ms: u32) -> Result<(), String> {
+            if self.pool_size() == 0 {
+                return Err(String::from(
+                    "kafka.transactions.pool_size must be positive",
+                ));
+            }
+            if self.id_prefix().trim().is_empty() {
+                return Err(String::from(
+                    "kafka.transactions.id_prefix must not be empty",
+                ));
+            }
+            if message_timeout_ms > self.timeout_ms() {
+                return Err(format!(
+                    "kafka.message_timeout_ms must not exceed kafka.transactions.timeout_ms ({}), got {}",
+                    self.timeout_ms(),
+                    message_timeout_ms
+                ));
+            }
+            Ok(())
+        }
+    }
+
+    /// CompressionCodec is a codec used to compress message batches.
+    #[derive(Debug, Clone, Copy, PartialEq, Deserialize)]
+    #[serde(rename_all = "lowercase")]
+    pub enum CompressionCodec {
+        None,
+        Gzip,
+        Snappy,
+        Lz4,
+        Zstd,
+    }
+
+    impl CompressionCodec {
+        pub fn as_str(&self) -> &'static str {
+            match self {
+                CompressionCodec::None => "none",
+                CompressionCodec::Gzip => "gzip",
+                CompressionCodec::Snappy => "snappy",
+                CompressionCodec::Lz4 => "lz4",
+                CompressionCodec::Zstd => "zstd",
+            }
+        }
+
+        /// Returns range of levels supported by codec, if codec has levels.
+        fn levels(&self) -> Option<std::ops::RangeInclusive<i32>> {
+            match self {
+                CompressionCodec::None | CompressionCodec::Snappy => None,
+                CompressionCodec::Gzip => Some(0..=9),
+                CompressionCodec::Lz4 | CompressionCodec::Zstd => Some(0..=12),
+            }
+        }
+    }
+
+    /// CompressionConfig describes compression of topic which differs from
+    /// the default one.
+    #[derive(Debug, Clone, PartialEq, Deserialize)]
+    pub struct CompressionConfig {
+        pub codec: CompressionCodec,
+
+        // Codec default level is used if not set.
+        #[serde(default)]
+        pub level: Option<i32>,
+    }
+
+    impl CompressionConfig {
+        fn validate(&self) -> Result<(), String> {
+            let level = match self.level {
+                None | Some(-1) => return Ok(()),
+                Some(level) => level,
+            };
+            match self.codec.levels() {
+                Some(levels) if levels.contains(&level) => Ok(()),
+                Some(levels) => Err(format!(
+                    "compression level of '{}' must be in range [{}, {}], got {}",
+                    self.codec.as_str(),
+                    levels.start(),
+                    levels.end(),
+                    level
+                )),
+                None => Err(format!(
+                    "compression level is not supported by '{}'",
+                    self.codec.as_str()
+                )),
+            }
+        }
+
+        /// Returns name of compression used in metrics, e.g. 'zstd:6'.
+        pub fn name(&self) -> String {
+            match self.level {
+                Some(level) => format!("{}:{}", self.codec.as_str(), level),
+                None => self.codec.as_str().to_string(),
+            }
+        }
+
+        /// Returns librdkafka properties of compression.
+        pub fn properties(&self) -> Vec<(&'static str, String)> {
+            let mut properties = vec![("compression.codec", self.codec.as_str().to_string())];
+            if let Some(level) = self.level {
+                properties.push(("compression.level", level.to_string()));
+            }
+            properties
+        }
+    }
+
+    /// SecurityProtocol is a protocol used to communicate with brokers.
+    #[derive(Debug, Clone, Copy, PartialEq, Deserialize)]
+    #[serde(rename_all = "snake_case")]
+    pub enum SecurityProtocol {
+        Plaintext,
+        Ssl,
+        SaslPlaintext,
+        SaslSsl,
+    }
+
+    impl SecurityProtocol {
+        pub fn as_str(&self) -> &'static str {
+            match self {
+                SecurityProtocol::Plaintext => "plaintext",
+                SecurityProtocol::Ssl => "ssl",
+                SecurityProtocol::SaslPlaintext => "sasl_plaintext",
+                SecurityProtocol::SaslSsl => "sasl_ssl",
+            }
+        }
+
+        fn is_sasl(&self) -> bool {
+            matches!(
+                self,
+                SecurityProtocol::SaslPlaintext | SecurityProtocol::SaslSsl
+            )
+        }
+
+        fn is_ssl(&self) -> bool {
+            matches!(self, SecurityProtocol::Ssl | SecurityProtocol::SaslSsl)
+        }
+    }
+
+    /// SaslMechanism is a SASL mechanism used for authentication in brokers.
+    #[derive(Debug, Clone, Copy, PartialEq, Deserialize)]
+    pub enum SaslMechanism {
+        #[serde(rename = "PLAIN")]
+        Plain,
+        #[serde(rename = "SCRAM-SHA-256")]
+        ScramSha256,
+        #[serde(rename = "SCRAM-SHA-512")]
+        ScramSha512,
+        #[serde(rename = "OAUTHBEARER")]
+        OAuthBearer,
+    }
+
+    impl SaslMechanism {
+        pub fn as_str(&self) -> &'static str {
+            match self {
+                SaslMechanism::Plain => "PLAIN",
+                SaslMechanism::ScramSha256 => "SCRAM-SHA-256",
+                SaslMechanism::ScramSha512 => "SCRAM-SHA-512",
+                SaslMechanism::OAuthBearer => "OAUTHBEARER",
+            }
+        }
+    }
 
     #[derive(Debug, Clone, Deserialize)]
     pub struct KafkaConfig {
@@ -36,7 +303,9 @@ pub mod config {
         #[serde(default = "KafkaConfig::default_message_timeout_ms")]
         pub message_timeout_ms: Option<u32>,
 
-        #[serde(default = "KafkaConfig::default_request_required_acks")]
+        // NOTE: not set by default, so that idempotent producer uses
+        // librdkafka default 'all', and explicit value is checked.
+        #[serde(default)]
         pub request_required_acks: Option<i32>,
 
         #[serde(default = "KafkaConfig::default_request_timeout_ms")]
@@ -44,6 +313,63 @@ pub mod config {
 
         #[serde(default = "KafkaConfig::default_statistics_interval_ms")]
         pub statistics_interval_ms: Option<u32>,
+
+        // librdkafka default is used if not set.
+        #[serde(default)]
+        pub allow_auto_create_topics: Option<bool>,
+
+        // Plaintext is used if not set.
+        #[serde(default)]
+        pub security_protocol: Option<SecurityProtocol>,
+
+        #[serde(default)]
+        pub sasl_mechanism: Option<SaslMechanism>,
+
+        // Alias for 'sasl.oauthbearer.config', used by OAUTHBEARER mechanism.
+        #[serde(default)]
+        pub sasl_oauthbearer_config: Option<String>,
+
+        #[serde(default)]
+        pub ssl_ca_location: Option<String>,
+
+        #[serde(default)]
+        pub ssl_certificate_location: Option<String>,
+
+        #[serde(default)]
+        pub ssl_key_location: Option<String>,
+
+        #[serde(default)]
+        pub ssl_key_password: Option<String>,
+
+        // Verify that broker hostname matches its certificate. librdkafka
+        // default is used if not set.
+        #[serde(default)]
+        pub ssl_endpoint_identification: Option<bool>,
+
+        // Arbitrary librdkafka properties, which are not managed by kprf.
+        #[serde(default)]
+        pub librdkafka_overrides: HashMap<String, String>,
+
+        // librdkafka default ('none') is used if not set.
+        #[serde(default)]
+        pub compression: Option<CompressionCodec>,
+
+        #[serde(default)]
+        pub compression_level: Option<i32>,
+
+        // Compression of topics which differs from the default one.
+        #[serde(default)]
+        pub topic_compression: HashMap<String, CompressionConfig>,
+
+        // Enables idempotent producer. Explicit acks, in-flight limit and
+        // retries which are inconsistent with idempotence are rejected.
+        #[serde(default)]
+        pub idempotent: bool,
+
+        // Transactional producers used by atomic pushes. Transactional
+        // producers are always idempotent.
+        #[serde(default)]
+        pub transactions: TransactionsConfig,
     }
 
     impl Default for KafkaConfig {
@@ -58,9 +384,24 @@ pub mod config {
                 queue_buffering_max_kbytes: KafkaConfig::default_queue_buffering_max_kbytes(),
                 retries: KafkaConfig::default_retries(),
                 message_timeout_ms: KafkaConfig::default_message_timeout_ms(),
-                request_required_acks: KafkaConfig::default_request_required_acks(),
+                request_required_acks: None,
                 request_timeout_ms: KafkaConfig::default_request_timeout_ms(),
                 statistics_interval_ms: KafkaConfig::default_statistics_interval_ms(),
+                allow_auto_create_topics: None,
+                security_protocol: None,
+                sasl_mechanism: None,
+                sasl_oauthbearer_config: None,
+                ssl_ca_location: None,
+                ssl_certificate_location: None,
+                ssl_key_location: None,
+                ssl_key_password: None,
+                ssl_endpoint_identification: None,
+                librdkafka_overrides: HashMap::new(),
+                compression: None,
+                compression_level: None,
+                topic_compression: HashMap::new(),
+                idempotent: false,
+                transactions: TransactionsConfig::default(),
             };
         }
     }
@@ -90,10 +431,6 @@ pub mod config {
             Some(DEFAULT_MESSAGE_TIMEOUT_MS)
         }
 
-        fn default_request_required_acks() -> Option<i32> {
-            Some(DEFAULT_REQUEST_REQUIRED_ACKS)
-        }
-
         fn default_request_timeout_ms() -> Option<u32> {
             Some(DEFAULT_REQUEST_TIMEOUT_MS)
         }
@@ -102,6 +439,220 @@ pub mod config {
             Some(DEFAULT_STATISTICS_INTERVAL_MS)
         }
 
+        /// Checks that security settings are consistent with each other, SSL
+        /// files exist and overrides do not contain managed properties.
+        pub fn validate(&self) -> Result<(), String> {
+            let mut overrides = self.librdkafka_overrides.keys().collect::<Vec<_>>();
+            overrides.sort();
+            if let Some(key) = overrides
+                .into_iter()
+                .find(|key| MANAGED_PROPERTIES.contains(&key.trim()))
+            {
+                return Err(format!(
+                    "librdkafka property '{}' is managed by kprf and can not be overridden",
+                    key
+                ));
+            }
+
+            if self.idempotent || self.transactions.enabled {
+                self.validate_idempotence()?;
+            }
+            if self.transactions.enabled {
+                self.transactions.validate(
+                    self.message_timeout_ms
+                        .unwrap_or(DEFAULT_MESSAGE_TIMEOUT_MS),
+                )?;
+            }
+            if let Some(compression) = self.default_compression() {
+                compression.validate()?;
+            }
+            for (topic, compression) in self.topic_compression.iter() {
+                compression
+                    .validate()
+                    .map_err(|e| format!("topic '{}': {}", topic, e))?;
+            }
+
+            let protocol = self
+                .security_protocol()
+                .unwrap_or(SecurityProtocol::Plaintext);
+
+            if protocol.is_sasl() {
+                let mechanism = self.sasl_mechanism().ok_or_else(|| {
+                    format!(
+                        "kafka.sasl_mechanism is required for '{}' security protocol",
+                        protocol.as_str()
+                    )
+                })?;
+                if mechanism != SaslMechanism::OAuthBearer
+                    && (self.user.is_none() || self.password.is_none())
+                {
+                    return Err(format!(
+                        "kafka.user and kafka.password are required for '{}' sasl mechanism",
+                        mechanism.as_str()
+                    ));
+                }
+                if mechanism != SaslMechanism::OAuthBearer && self.sasl_oauthbearer_config.is_some()
+                {
+                    return Err(String::from(
+                        "kafka.sasl_oauthbearer_config requires 'OAUTHBEARER' sasl mechanism",
+                    ));
+                }
+            } else if self.sasl_mechanism.is_some()
+                || self.sasl_oauthbearer_config.is_some()
+                || self.user.is_some()
+                || self.password.is_some()
+            {
+                return Err(format!(
+                    "sasl settings are set, but security protocol is '{}'",
+                    protocol.as_str()
+                ));
+            }
+
+            let ssl_paths = [
+                &self.ssl_ca_location,
+                &self.ssl_certificate_location,
+                &self.ssl_key_location,
+            ];
+            if !protocol.is_ssl() {
+                if ssl_paths.iter().any(|p| p.is_some())
+                    || self.ssl_key_password.is_some()
+                    || self.ssl_endpoint_identification.is_some()
+                {
+                    return Err(format!(
+                        "ssl settings are set, but security protocol is '{}'",
+                        protocol.as_str()
+                    ));
+                }
+                return Ok(());
+            }
+
+            if self.ssl_certificate_location.is_some() != self.ssl_key_location.is_some() {
+                return Err(String::from(
+                    "kafka.ssl_certificate_location and kafka.ssl_key_location must be set together",
+                ));
+            }
+            for path in ssl_paths.iter().filter_map(|p| p.as_ref()) {
+                std::fs::metadata(path).map_err(|e| format!("'{}': {}", path, e))?;
+            }
+            Ok(())
+        }
+
+        fn validate_idempotence(&self) -> Result<(), String> {
+            if self.retries == Some(0) {
+                return Err(String::from(
+                    "kafka.retries must be positive for idempotent producer",
+                ));
+            }
+            // NOTE: transactional producers always use acks 'all', acks of
+            // the default producer is checked only if it is idempotent.
+            match self.request_required_acks {
+                Some(acks) if self.idempotent && acks != -1 => {
+                    return Err(format!(
+                        "kafka.request_required_acks must be -1 for idempotent producer, got {}",
+                        acks
+                    ))
+                }
+                _ => {}
+            }
+            for key in ["max.in.flight.requests.per.connection", "max.in.flight"].iter() {
+                let value = match self.librdkafka_overrides.get(*key) {
+                    None => continue,
+                    Some(value) => value,
+                };
+                match value.trim().parse::<u32>() {
+                    Ok(n) if (1..=IDEMPOTENT_MAX_IN_FLIGHT).contains(&n) => {}
+                    _ => {
+                        return Err(format!(
+                            "librdkafka property '{}' must be in range [1, {}] for idempotent producer, got '{}'",
+                            key, IDEMPOTENT_MAX_IN_FLIGHT, value
+                        ))
+                    }
+                }
+            }
+            Ok(())
+        }
+
+        /// Returns true if only `user` and `password` are set, as in configs
+        /// made before security settings were supported.
+        fn legacy_sasl(&self) -> bool {
+            self.security_protocol.is_none()
+                && self.sasl_mechanism.is_none()
+                && (self.user.is_some() || self.password.is_some())
+        }
+
+        /// Returns configured security protocol. Legacy configs with
+        /// credentials only use 'sasl_plaintext'.
+        pub fn security_protocol(&self) -> Option<SecurityProtocol> {
+            if self.legacy_sasl() {
+                return Some(SecurityProtocol::SaslPlaintext);
+            }
+            self.security_protocol
+        }
+
+        /// Returns configured sasl mechanism. Legacy configs with credentials
+        /// only use 'PLAIN'.
+        pub fn sasl_mechanism(&self) -> Option<SaslMechanism> {
+            if self.legacy_sasl() {
+                return Some(SaslMechanism::Plain);
+            }
+            self.sasl_mechanism
+        }
+
+        /// Returns compression used for topics without overrides, if it is
+        /// configured.
+        pub fn default_compression(&self) -> Option<CompressionConfig> {
+            if self.compression.is_none() && self.compression_level.is_none() {
+                return None;
+            }
+            Some(CompressionConfig {
+                codec: self.compression.unwrap_or(CompressionCodec::None),
+                level: self.compression_level,
+            })
+        }
+
+        /// Returns librdkafka client config made of typed options and
+        /// overrides. Unknown and invalid properties are reported by
+        /// librdkafka when client is created.
+        pub fn client_config(&self) -> rdkafka::ClientConfig {
+            let mut client_config = rdkafka::ClientConfig::new();
+            for (k, v) in self.to_hash().iter() {
+                client_config.set(k, v);
+            }
+            for (k, v) in self.librdkafka_overrides.iter() {
+                client_config.set(k, v);
+            }
+            client_config
+        }
+
+        /// Returns 'transactional.id' of producer with the given number in
+        /// transactions pool.
+        pub fn transactional_id(&self, n: u32) -> String {
+            format!("{}-{}", self.transactions.id_prefix(), n)
+        }
+
+        /// Returns librdkafka client config of producer with the given number
+        /// in transactions pool.
+        pub fn transactional_client_config(&self, n: u32) -> rdkafka::ClientConfig {
+            let mut client_config = self.client_config();
+            client_config.set("transactional.id", &self.transactional_id(n));
+            client_config.set(
+                "transaction.timeout.ms",
+                &self.transactions.timeout_ms().to_string(),
+            );
+            client_config.set("enable.idempotence", "true");
+            client_config.set("request.required.acks", "-1");
+            // NOTE: in-flight limit is adjusted by librdkafka unless it is
+            // overridden, overrides are already checked to be valid for
+            // idempotent producer.
+            if !self.idempotent && self.statistics_interval_ms == Some(0) {
+                client_config.set(
+                    "statistics.interval.ms",
+                    &IDEMPOTENT_STATISTICS_INTERVAL_MS.to_string(),
+                );
+            }
+            client_config
+        }
+
         pub fn to_hash(&self) -> HashMap<String, String> {
             let mut mp: HashMap<String, String> = HashMap::new();
             mp.insert(String::from("bootstrap.servers"), self.brokers.join(","));
@@ -138,10 +689,19 @@ pub mod config {
                 String::from("request.timeout.ms"),
                 self.request_timeout_ms.unwrap().to_string(),
             );
-            mp.insert(
-                String::from("request.required.acks"),
-                self.request_required_acks.unwrap().to_string(),
-            );
+            // NOTE: librdkafka uses acks 'all' for idempotent producer.
+            match self.request_required_acks {
+                Some(acks) => {
+                    mp.insert(String::from("request.required.acks"), acks.to_string());
+                }
+                None if !self.idempotent => {
+                    mp.insert(
+                        String::from("request.required.acks"),
+                        DEFAULT_REQUEST_REQUIRED_ACKS.to_string(),
+                    );
+                }
+                None => {}
+            }
             mp.insert(
                 String::from("queue.buffering.max.kbytes"),
                 self.queue_buffering_max_kbytes.unwrap().to_string(),
@@ -150,6 +710,54 @@ pub mod config {
                 String::from("statistics.interval.ms"),
                 self.statistics_interval_ms.unwrap().to_string(),
             );
+            if let Some(protocol) = self.security_protocol() {
+                mp.insert(
+                    String::from("security.protocol"),
+                    protocol.as_str().to_string(),
+                );
+            }
+            if let Some(mechanism) = self.sasl_mechanism() {
+                mp.insert(
+                    String::from("sasl.mechanism"),
+                    mechanism.as_str().to_string(),
+                );
+            }
+            let optional = [
+                ("sasl.oauthbearer.config", &self.sasl_oauthbearer_config),
+                ("ssl.ca.location", &self.ssl_ca_location),
+                ("ssl.certificate.location", &self.ssl_certificate_location),
+                ("ssl.key.location", &self.ssl_key_location),
+                ("ssl.key.password", &self.ssl_key_password),
+            ];
+            for (key, value) in optional.iter() {
+                if let Some(value) = value {
+                    mp.insert(key.to_string(), value.to_string());
+                }
+            }
+            if let Some(enabled) = self.ssl_endpoint_identification {
+                let algorithm = if enabled { "https" } else { "none" };
+                mp.insert(
+                    String::from("ssl.endpoint.identification.algorithm"),
+                    algorithm.to_string(),
+                );
+            }
+            if let Some(allow) = self.allow_auto_create_topics {
+                mp.insert(String::from("allow.auto.create.topics"), allow.to_string());
+            }
+            if let Some(compression) = self.default_compression() {
+                for (key, value) in compression.properties() {
+                    mp.insert(key.to_string(), value);
+                }
+            }
+            if self.idempotent {
+                mp.insert(String::from("enable.idempotence"), String::from("true"));
+                if self.statistics_interval_ms == Some(0) {
+                    mp.insert(
+                        String::from("statistics.interval.ms"),
+                        IDEMPOTENT_STATISTICS_INTERVAL_MS.to_string(),
+                    );
+                }
+            }
             return mp;
         }
     }
@@ -157,10 +765,15 @@ pub mod config {
 
 pub mod producer {
     use rdkafka::config::FromClientConfigAndContext;
-    use rdkafka::producer::future_producer::OwnedDeliveryResult;
-    use rdkafka::producer::{FutureProducer, FutureRecord};
-    use rdkafka::{ClientContext, Statistics};
-    use std::sync::Arc;
+    use rdkafka::error::{KafkaError, KafkaResult, RDKafkaErrorCode};
+    use rdkafka::message::{Message as _, OwnedHeaders, OwnedMessage};
+    use rdkafka::producer::{
+        BaseRecord, DeliveryResult, Producer as _, ProducerContext, ThreadedProducer,
+    };
+    use rdkafka::{ClientConfig, ClientContext, Statistics, Timestamp};
+    use serde::Serialize;
+    use std::collections::HashMap;
+    use std::sync::{Arc, Mutex};
     use std::time::{Duration, SystemTime};
 
     const BROKER_STATE_INIT: i64 = 0;
@@ -172,7 +785,52 @@ pub mod producer {
     const BROKER_STATE_UP: i64 = 6;
     const BROKER_STATE_UPDATE: i64 = 7;
 
+    /// Interval between attempts to enqueue a message while producer queue is
+    /// full.
+    const QUEUE_FULL_RETRY_INTERVAL: Duration = Duration::from_millis(100);
+
+    /// InstanceStatus describes idempotence state of a single librdkafka
+    /// producer instance.
+    #[derive(Debug, Clone, Serialize)]
+    pub struct InstanceStatus {
+        pub compression: String,
+        /// librdkafka idempotent producer state, e.g. 'Assigned'.
+        pub idempotence_state: String,
+        /// Producer id assigned by broker, or -1.
+        pub producer_id: i64,
+        /// Epoch of producer id, or -1.
+        pub producer_epoch: i64,
+        #[serde(skip_serializing_if = "Option::is_none")]
+        pub transactional_id: Option<String>,
+    }
+
+    /// Status describes state of the producer reported by status endpoint.
+    #[derive(Debug, Serialize)]
+    pub struct Status {
+        pub idempotent: bool,
+        pub transactions: bool,
+        /// Instances are reported as soon as their first statistics are
+        /// received.
+        pub instances: Vec<InstanceStatus>,
+    }
+
+    #[derive(Clone)]
     struct KprfClientContext {
+        /// Name of compression used by the producer, e.g. 'lz4'.
+        compression: String,
+        /// Set only for transactional producers.
+        transactional_id: Option<String>,
+        /// Latest idempotence state of producer instances, shared between
+        /// all of them.
+        instances: Arc<Mutex<HashMap<String, InstanceStatus>>>,
+        /// Whether any broker of producer instance is UP, per instance.
+        brokers_up: Arc<Mutex<HashMap<String, bool>>>,
+        /// Ratio of produced messages size to size of data transmitted to
+        /// brokers. It is approximate, because transmitted data includes
+        /// protocol overhead.
+        ///
+        /// librdkafka 'txmsg_bytes' / 'tx_bytes'
+        compression_ratio: prometheus::GaugeVec,
         /// Number of operations (callbacks, events, etc.) waiting in queue.
         ///
         /// librdkafka 'replyq'
@@ -212,7 +870,7 @@ pub mod producer {
         /// Number of topics in the metadata cache.
         ///
         /// librdkafka 'metadata_cache_count'
-        metadata_cache_topics_count: prometheus::IntGauge,
+        metadata_cache_topics_count: prometheus::IntGaugeVec,
         /// Broker state (INIT, DOWN, CONNECT, AUTH, APIVERSION_QUERY,
         /// AUTH_HANDSHAKE, UP, UPDATE).
         ///
@@ -301,15 +959,51 @@ pub mod producer {
             self.total_messages_sent.inc_by(statistics.txmsgs as u64);
             self.total_messages_sent_bytes
                 .inc_by(statistics.txmsg_bytes as u64);
+            // NOTE: gauges are labelled by instance, because instances of the
+            // producer share metrics and report statistics independently.
+            let name = self.instance();
             self.metadata_cache_topics_count
+                .with_label_values(&[&name])
                 .set(statistics.metadata_cache_cnt);
+            if let Some(eos) = &statistics.eos {
+                let status = InstanceStatus {
+                    compression: self.compression.clone(),
+                    idempotence_state: eos.idemp_state.clone(),
+                    producer_id: eos.producer_id,
+                    producer_epoch: eos.producer_epoch,
+                    transactional_id: self.transactional_id.clone(),
+                };
+                if let Ok(mut instances) = self.instances.lock() {
+                    instances.insert(name.clone(), status);
+                }
+            }
+            // NOTE: internal and logical brokers do not reflect availability
+            // of the cluster.
+            let up = statistics.brokers.values().any(|broker| {
+                if broker.source == "internal" || broker.source == "logical" {
+                    return false;
+                }
+                let state = KprfClientContext::parse_state(&broker.state);
+                state == BROKER_STATE_UP || state == BROKER_STATE_UPDATE
+            });
+            if let Ok(mut brokers_up) = self.brokers_up.lock() {
+                brokers_up.insert(name.clone(), up);
+            }
+            if statistics.tx_bytes > 0 {
+                self.compression_ratio
+                    .with_label_values(&[&self.compression, &name])
+                    .set(statistics.txmsg_bytes as f64 / statistics.tx_bytes as f64);
+            }
 
             for (k, v) in statistics.brokers.iter() {
                 let labels = [k.as_str()];
+                let instance_labels = [name.as_str(), k.as_str()];
                 let state = KprfClientContext::parse_state(&v.state);
-                self.broker_state.with_label_values(&labels).set(state);
+                self.broker_state
+                    .with_label_values(&instance_labels)
+                    .set(state);
                 self.broker_stateage
-                    .with_label_values(&labels)
+                    .with_label_values(&instance_labels)
                     .set(v.stateage);
                 self.broker_outbuf_count
                     .with_label_values(&labels)
@@ -349,7 +1043,7 @@ pub mod producer {
                     .inc_by(v.rxerrs as u64);
             }
             for (k, v) in statistics.topics.iter() {
-                let labels = [k.as_str()];
+                let labels = [name.as_str(), k.as_str()];
                 self.topic_metadata_age
                     .with_label_values(&labels)
                     .set(v.metadata_age);
@@ -363,6 +1057,23 @@ pub mod producer {
         }
     }
 
+    impl ProducerContext for KprfClientContext {
+        type DeliveryOpaque = Box<tokio::sync::oneshot::Sender<OwnedDeliveryResult>>;
+
+        fn delivery(&self, delivery_result: &DeliveryResult<'_>, tx: Self::DeliveryOpaque) {
+            let result = match delivery_result {
+                Ok(message) => Ok(Delivery {
+                    partition: message.partition(),
+                    offset: message.offset(),
+                    timestamp: message.timestamp().to_millis(),
+                }),
+                Err((err, message)) => Err((err.clone(), message.detach())),
+            };
+            // NOTE: receiver is dropped if request was cancelled.
+            let _ = tx.send(result);
+        }
+    }
+
     impl KprfClientContext {
         fn parse_state(state: &String) -> i64 {
             return match state.as_str() {
@@ -378,196 +1089,589 @@ pub mod producer {
             };
         }
 
-        fn new() -> KprfClientContext {
+        /// Returns name of producer instance: transactional id of
+        /// transactional producer, otherwise its compression.
+        fn instance(&self) -> String {
+            self.transactional_id
+                .clone()
+                .unwrap_or_else(|| self.compression.clone())
+        }
+
+        /// Returns context of another producer instance, which shares
+        /// metrics with this one.
+        fn with_compression(&self, compression: String) -> KprfClientContext {
+            KprfClientContext {
+                compression,
+                ..self.clone()
+            }
+        }
+
+        /// Returns context of transactional producer instance, which shares
+        /// metrics with this one.
+        fn with_transactional_id(&self, transactional_id: String) -> KprfClientContext {
             KprfClientContext {
+                transactional_id: Some(transactional_id),
+                ..self.clone()
+            }
+        }
+
+        fn new(cluster: &str, compression: String) -> KprfClientContext {
+            KprfClientContext {
+                compression,
+                transactional_id: None,
+                instances: Arc::new(Mutex::new(HashMap::new())),
+                brokers_up: Arc::new(Mutex::new(HashMap::new())),
+                compression_ratio: prometheus::register_gauge_vec!(
+                    prometheus::opts!("kafka_producer_compression_ratio",
+                    "Kafka producer ratio of produced messages size to size of data sent to brokers").const_label("cluster", cluster),
+                    &["compression", "instance"]
+                )
+                .unwrap(),
                 reply_queue_size: prometheus::register_int_counter!(
-                    "kafka_producer_reply_queue_size",
-                    "Kafka producer reply queue size"
+                    prometheus::opts!("kafka_producer_reply_queue_size",
+                    "Kafka producer reply queue size").const_label("cluster", cluster)
                 )
                 .unwrap(),
                 current_messages_in_queue: prometheus::register_int_counter!(
-                    "kafka_producer_current_messages_in_queue",
-                    "Kafka producer messages currently in queue"
+                    prometheus::opts!("kafka_producer_current_messages_in_queue",
+                    "Kafka producer messages currently in queue").const_label("cluster", cluster)
                 )
                 .unwrap(),
                 current_messages_in_queue_bytes: prometheus::register_int_counter!(
-                    "kafka_producer_current_messages_in_queue_bytes",
-                    "Kafka producer messages currently in queue as bytes"
+                    prometheus::opts!("kafka_producer_current_messages_in_queue_bytes",
+                    "Kafka producer messages currently in queue as bytes").const_label("cluster", cluster)
                 )
                 .unwrap(),
                 total_requests_count: prometheus::register_int_counter!(
-                    "kafka_producer_total_requests_count",
-                    "Kafka producer total number of requests sent to brokers"
+                    prometheus::opts!("kafka_producer_total_requests_count",
+                    "Kafka producer total number of requests sent to brokers").const_label("cluster", cluster)
                 )
                 .unwrap(),
                 total_bytes_sent: prometheus::register_int_counter!(
-                    "kafka_producer_total_bytes_sent",
-                    "Kafka producer total number of bytes transmitted to brokers"
+                    prometheus::opts!("kafka_producer_total_bytes_sent",
+                    "Kafka producer total number of bytes transmitted to brokers").const_label("cluster", cluster)
                 )
                 .unwrap(),
                 total_responses_received: prometheus::register_int_counter!(
-                    "kafka_producer_total_responses_received",
-                    "Kafka producer total number of responses received from brokers"
+                    prometheus::opts!("kafka_producer_total_responses_received",
+                    "Kafka producer total number of responses received from brokers").const_label("cluster", cluster)
                 )
                 .unwrap(),
                 total_bytes_received: prometheus::register_int_counter!(
-                    "kafka_producer_total_bytes_received",
-                    "Kafka producer total number of bytes received from brokers"
+                    prometheus::opts!("kafka_producer_total_bytes_received",
+                    "Kafka producer total number of bytes received from brokers").const_label("cluster", cluster)
                 )
                 .unwrap(),
                 total_messages_sent: prometheus::register_int_counter!(
-                    "kafka_producer_total_messages_sent",
-                    "Kafka producer total number of messages transmitted (produced) to brokers"
+                    prometheus::opts!("kafka_producer_total_messages_sent",
+                    "Kafka producer total number of messages transmitted (produced) to brokers").const_label("cluster", cluster)
                 )
                 .unwrap(),
                 total_messages_sent_bytes: prometheus::register_int_counter!(
-                    "kafka_producer_total_messages_bytes_sent",
-                    "Kafka producer total number of bytes transmitted (produced) to brokers"
+                    prometheus::opts!("kafka_producer_total_messages_bytes_sent",
+                    "Kafka producer total number of bytes transmitted (produced) to brokers").const_label("cluster", cluster)
                 )
                 .unwrap(),
-                metadata_cache_topics_count: prometheus::register_int_gauge!(
-                    "kafka_producer_metadata_cache_topics_count",
-                    "Kafka producer number of topics in the metadata cache"
+                metadata_cache_topics_count: prometheus::register_int_gauge_vec!(
+                    prometheus::opts!("kafka_producer_metadata_cache_topics_count",
+                    "Kafka producer number of topics in the metadata cache").const_label("cluster", cluster),
+                    &["instance"]
                 )
                 .unwrap(),
                 broker_state: prometheus::register_int_gauge_vec!(
-                    "kafka_producer_broker_state",
-                    "Kafka producer broker state",
-                    &["broker"]
+                    prometheus::opts!("kafka_producer_broker_state",
+                    "Kafka producer broker state").const_label("cluster", cluster),
+                    &["instance", "broker"]
                 )
                 .unwrap(),
                 broker_stateage: prometheus::register_int_gauge_vec!(
-                    "kafka_producer_broker_state_age",
-                    "Kafka producer time since the last broker state change, in microseconds",
-                    &["broker"]
+                    prometheus::opts!("kafka_producer_broker_state_age",
+                    "Kafka producer time since the last broker state change, in microseconds").const_label("cluster", cluster),
+                    &["instance", "broker"]
                 )
                 .unwrap(),
                 broker_outbuf_count: prometheus::register_int_counter_vec!(
-                    "kafka_producer_broker_outbuf_count",
-                    "Kafka producer number of requests awaiting transmission to the broker",
+                    prometheus::opts!("kafka_producer_broker_outbuf_count",
+                    "Kafka producer number of requests awaiting transmission to the broker").const_label("cluster", cluster),
                     &["broker"]
                 )
                 .unwrap(),
                 broker_outbuf_msg_count: prometheus::register_int_counter_vec!(
-                    "kafka_producer_broker_outbuf_msg_count",
-                    "Kafka producer number of messages awaiting transmission to the broker",
+                    prometheus::opts!("kafka_producer_broker_outbuf_msg_count",
+                    "Kafka producer number of messages awaiting transmission to the broker").const_label("cluster", cluster),
                     &["broker"]
                 )
                 .unwrap(),
                 broker_waitresp_count: prometheus::register_int_counter_vec!(
-                    "kafka_producer_broker_waitresp_count",
-                    "Kafka producer number of requests awaiting transmission to the broker",
+                    prometheus::opts!("kafka_producer_broker_waitresp_count",
+                    "Kafka producer number of requests awaiting transmission to the broker").const_label("cluster", cluster),
                     &["broker"]
                 )
                 .unwrap(),
                 broker_waitresp_msg_count: prometheus::register_int_counter_vec!(
-                    "kafka_producer_broker_waitresp_msg_count",
-                    "Kafka producer total number of requests sent to the broker",
+                    prometheus::opts!("kafka_producer_broker_waitresp_msg_count",
+                    "Kafka producer total number of requests sent to the broker").const_label("cluster", cluster),
                     &["broker"]
                 )
                 .unwrap(),
                 broker_requests_sent: prometheus::register_int_counter_vec!(
-                    "kafka_producer_broker_requests_sent",
-                    "Kafka producer total number of requests sent to the broker",
+                    prometheus::opts!("kafka_producer_broker_requests_sent",
+                    "Kafka producer total number of requests sent to the broker").const_label("cluster", cluster),
                     &["broker"]
                 )
                 .unwrap(),
                 broker_requests_sent_bytes: prometheus::register_int_counter_vec!(
-                    "kafka_producer_broker_requests_sent_bytes",
-                    "Kafka producer total number of bytes sent to the broker",
+                    prometheus::opts!("kafka_producer_broker_requests_sent_bytes",
+                    "Kafka producer total number of bytes sent to the broker").const_label("cluster", cluster),
                     &["broker"]
                 )
                 .unwrap(),
                 broker_transmission_errors: prometheus::register_int_counter_vec!(
-                    "kafka_producer_broker_transmission_errors",
-                    "Kafka producer total number of transmission errors",
+                    prometheus::opts!("kafka_producer_broker_transmission_errors",
+                    "Kafka producer total number of transmission errors").const_label("cluster", cluster),
                     &["broker"]
                 )
                 .unwrap(),
                 broker_request_retries: prometheus::register_int_counter_vec!(
-                    "kafka_producer_broker_request_retries",
-                    "Kafka producer total number of request retries",
+                    prometheus::opts!("kafka_producer_broker_request_retries",
+                    "Kafka producer total number of request retries").const_label("cluster", cluster),
                     &["broker"]
                 )
                 .unwrap(),
                 broker_request_timeouts: prometheus::register_int_counter_vec!(
-                    "kafka_producer_request_timeouts",
-                    "Kafka producer total number of requests that timed out",
+                    prometheus::opts!("kafka_producer_request_timeouts",
+                    "Kafka producer total number of requests that timed out").const_label("cluster", cluster),
                     &["broker"]
                 )
                 .unwrap(),
                 broker_responses_count: prometheus::register_int_counter_vec!(
-                    "kafka_producer_broker_responses_count",
-                    "Kafka producer total number of responses received from the broker",
+                    prometheus::opts!("kafka_producer_broker_responses_count",
+                    "Kafka producer total number of responses received from the broker").const_label("cluster", cluster),
                     &["broker"]
                 )
                 .unwrap(),
                 broker_bytes_received: prometheus::register_int_counter_vec!(
-                    "kafka_producer_broker_bytes_received",
-                    "Kafka producer total number of bytes received from the broker",
+                    prometheus::opts!("kafka_producer_broker_bytes_received",
+                    "Kafka producer total number of bytes received from the broker").const_label("cluster", cluster),
                     &["broker"]
                 )
                 .unwrap(),
                 broker_errors_count: prometheus::register_int_counter_vec!(
-                    "kafka_producer_broker_errors_count",
-                    "Kafka producer total number of received errors",
+                    prometheus::opts!("kafka_producer_broker_errors_count",
+                    "Kafka producer total number of received errors").const_label("cluster", cluster),
                     &["broker"]
                 )
                 .unwrap(),
                 topic_metadata_age: prometheus::register_int_gauge_vec!(
-                    "kafka_producer_topic_metadata_age",
-                    "Kafka producer age of the client's metadata for this topic, in milliseconds",
-                    &["topic"]
+                    prometheus::opts!("kafka_producer_topic_metadata_age",
+                    "Kafka producer age of the client's metadata for this topic, in milliseconds").const_label("cluster", cluster),
+                    &["instance", "topic"]
                 )
                 .unwrap(),
                 topic_batchsize_avg: prometheus::register_int_gauge_vec!(
-                    "kafka_producer_topic_batchsize_avg",
-                    "Kafka producer rolling window statistics for batch sizes, in bytes",
-                    &["topic"]
+                    prometheus::opts!("kafka_producer_topic_batchsize_avg",
+                    "Kafka producer rolling window statistics for batch sizes, in bytes").const_label("cluster", cluster),
+                    &["instance", "topic"]
                 )
                 .unwrap(),
                 topic_batchcount_avg: prometheus::register_int_gauge_vec!(
-                    "kafka_producer_topic_batchcount_avg",
-                    "Kafka producer rolling window statistics for batch message counts",
-                    &["topic"]
+                    prometheus::opts!("kafka_producer_topic_batchcount_avg",
+                    "Kafka producer rolling window statistics for batch message counts").const_label("cluster", cluster),
+                    &["instance", "topic"]
                 )
                 .unwrap(),
             }
         }
     }
 
+    /// Delivery describes a delivered message.
+    #[derive(Debug, Clone, Copy, PartialEq)]
+    pub struct Delivery {
+        pub partition: i32,
+        pub offset: i64,
+        /// Timestamp of the message reported by broker, in milliseconds since
+        /// epoch. It is the time message was appended to the log for topics
+        /// with `LogAppendTime` timestamp type.
+        pub timestamp: Option<i64>,
+    }
+
+    /// Result of producing a message. Message which failed is returned
+    /// together with the error.
+    pub type OwnedDeliveryResult = Result<Delivery, (KafkaError, OwnedMessage)>;
+
+    /// KafkaProducer is a librdkafka producer instance, which reports delivery
+    /// results through its context.
+    type KafkaProducer = Arc<ThreadedProducer<KprfClientContext>>;
+
+    fn new_kafka_producer(
+        client_config: &ClientConfig,
+        context: KprfClientContext,
+    ) -> KafkaResult<KafkaProducer> {
+        ThreadedProducer::from_config_and_context(client_config, context).map(Arc::new)
+    }
+
+    /// Message describes a single record to be produced.
+    #[derive(Clone)]
+    pub struct Message<'a> {
+        pub topic: &'a str,
+        /// None produces a tombstone.
+        pub payload: Option<&'a [u8]>,
+        pub key: Option<&'a [u8]>,
+        pub partition: Option<i32>,
+        pub headers: Option<OwnedHeaders>,
+        /// Message timestamp in milliseconds since epoch. If not set, it is
+        /// assigned by librdkafka or, for topics with `LogAppendTime`
+        /// timestamp type, by broker.
+        pub timestamp: Option<i64>,
+    }
+
+    /// TransactionOutcome describes the result of producing messages in a
+    /// single transaction.
+    pub struct TransactionOutcome {
+        /// Delivery results of messages, in order they were passed. Messages
+        /// of aborted transaction may be reported as delivered, but they are
+        /// never visible to transactional consumers.
+        pub results: Vec<OwnedDeliveryResult>,
+        /// Error of the transaction itself, e.g. failed commit.
+        pub error: Option<KafkaError>,
+        /// Error of the mirror transaction, which is run after this one is
+        /// committed. Committed messages stay delivered if it fails.
+        pub mirror_error: Option<KafkaError>,
+    }
+
+    impl TransactionOutcome {
+        pub fn committed(&self) -> bool {
+            self.error.is_none() && self.results.iter().all(|r| r.is_ok())
+        }
+    }
+
+    /// TransactionalProducer is a producer of transactions pool. Transactions
+    /// are initialized on first use, so that kprf starts with unavailable
+    /// brokers.
+    struct TransactionalProducer {
+        producer: KafkaProducer,
+        client_config: ClientConfig,
+        context: KprfClientContext,
+        initialized: bool,
+        // Whether transaction was begun and is not committed or aborted yet.
+        in_transaction: bool,
+    }
+
+    impl TransactionalProducer {
+        fn new(client_config: ClientConfig, context: KprfClientContext) -> TransactionalProducer {
+            let producer = new_kafka_producer(&client_config, context.clone())
+                .unwrap_or_else(|err| panic!("Failed to create transactional producer: {}", err));
+            TransactionalProducer {
+                producer,
+                client_config,
+                context,
+                initialized: false,
+                in_transaction: false,
+            }
+        }
+
+        /// Runs blocking librdkafka call outside of async runtime threads.
+        async fn blocking<F>(&self, f: F) -> KafkaResult<()>
+        where
+            F: FnOnce(&ThreadedProducer<KprfClientContext>) -> KafkaResult<()> + Send + 'static,
+        {
+            let producer = self.producer.clone();
+            tokio::task::spawn_blocking(move || f(&producer))
+                .await
+                .unwrap_or(Err(KafkaError::Canceled))
+        }
+
+        async fn begin(&mut self, timeout: Duration) -> KafkaResult<()> {
+            if !self.initialized {
+                if let Err(err) = self.blocking(move |p| p.init_transactions(timeout)).await {
+                    self.recreate();
+                    return Err(err);
+                }
+                self.initialized = true;
+            }
+            self.producer.begin_transaction()?;
+            self.in_transaction = true;
+            Ok(())
+        }
+
+        async fn commit(&mut self, timeout: Duration) -> KafkaResult<()> {
+            self.blocking(move |p| p.commit_transaction(timeout))
+                .await?;
+            self.in_transaction = false;
+            Ok(())
+        }
+
+        /// Aborts current transaction. If transaction can not be aborted,
+        /// producer is recreated, because its state is unknown.
+        async fn abort(&mut self, timeout: Duration) {
+            if self
+                .blocking(move |p| p.abort_transaction(timeout))
+                .await
+                .is_err()
+            {
+                self.recreate();
+            }
+            self.in_transaction = false;
+        }
+
+        fn recreate(&mut self) {
+            if let Ok(producer) = new_kafka_producer(&self.client_config, self.context.clone()) {
+                self.producer = producer;
+                self.initialized = false;
+            }
+        }
+    }
+
+    /// TransactionalPool limits number of concurrent transactions, every
+    /// transaction uses its own producer.
+    struct TransactionalPool {
+        producers: Arc<tokio::sync::Mutex<Vec<TransactionalProducer>>>,
+        available: Arc<tokio::sync::Semaphore>,
+        timeout: Duration,
+    }
+
+    impl TransactionalPool {
+        async fn acquire(&self) -> Option<PooledProducer> {
+            // NOTE: semaphore is never closed.
+            let permit = self.available.clone().acquire_owned().await.ok()?;
+            let producer = self.producers.lock().await.pop()?;
+            Some(PooledProducer {
+                producer: Some(producer),
+                producers: self.producers.clone(),
+                permit: Some(permit),
+                timeout: self.timeout,
+            })
+        }
+    }
+
+    /// PooledProducer returns transactional producer to the pool when it is
+    /// dropped. Transaction which is left open, e.g. because the request was
+    /// cancelled, is aborted first.
+    struct PooledProducer {
+        producer: Option<TransactionalProducer>,
+        producers: Arc<tokio::sync::Mutex<Vec<TransactionalProducer>>>,
+        permit: Option<tokio::sync::OwnedSemaphorePermit>,
+        timeout: Duration,
+    }
+
+    impl Drop for PooledProducer {
+        fn drop(&mut self) {
+            let mut producer = match self.producer.take() {
+                Some(producer) => producer,
+                None => return,
+            };
+            let producers = self.producers.clone();
+            let permit = self.permit.take();
+            let timeout = self.timeout;
+            tokio::spawn(async move {
+                if producer.in_transaction {
+                    producer.abort(timeout).await;
+                }
+                producers.lock().await.push(producer);
+                // NOTE: permit is released only after producer is returned.
+                drop(permit);
+            });
+        }
+    }
+
     pub struct Producer {
-        producer: FutureProducer<KprfClientContext>,
+        producer: KafkaProducer,
+        // Producers of topics with compression which differs from the
+        // default one.
+        topic_producers: HashMap<String, KafkaProducer>,
+        // None if transactions are disabled.
+        transactional: Option<TransactionalPool>,
+        idempotent: bool,
+        instances: Arc<Mutex<HashMap<String, InstanceStatus>>>,
+        brokers_up: Arc<Mutex<HashMap<String, bool>>>,
         sent_messages_counter: prometheus::IntCounterVec,
         queue_size_gauge: prometheus::IntGaugeVec,
         error_counter: prometheus::IntCounterVec,
         message_send_duration: prometheus::HistogramVec,
+        transactions_counter: prometheus::IntCounterVec,
     }
 
     impl Producer {
-        pub async fn send(
+        pub fn status(&self) -> Status {
+            let mut instances = self
+                .instances
+                .lock()
+                .map(|instances| instances.values().cloned().collect::<Vec<_>>())
+                .unwrap_or_default();
+            instances.sort_by(|a, b| {
+                (&a.compression, &a.transactional_id).cmp(&(&b.compression, &b.transactional_id))
+            });
+            Status {
+                idempotent: self.idempotent,
+                transactions: self.transactional.is_some(),
+                instances,
+            }
+        }
+
+        /// Returns true if any broker is UP according to the latest
+        /// statistics of producer instances, or None if no statistics were
+        /// received yet.
+        pub fn available(&self) -> Option<bool> {
+            let brokers_up = self.brokers_up.lock().ok()?;
+            if brokers_up.is_empty() {
+                return None;
+            }
+            Some(brokers_up.values().any(|up| *up))
+        }
+
+        /// Sends message and waits for its delivery result. If message is not
+        /// delivered within `timeout`, `MessageTimedOut` error is returned;
+        /// the message itself may still be delivered later.
+        pub async fn send(&self, message: Message<'_>, timeout: Duration) -> OwnedDeliveryResult {
+            let producer = self
+                .topic_producers
+                .get(message.topic)
+                .unwrap_or(&self.producer);
+            self.send_with(producer, message, timeout).await
+        }
+
+        /// Sends messages in a single transaction, which is committed only if
+        /// all of them are delivered. Waits for a free producer if all of them
+        /// are busy. Returns None if transactions are disabled.
+        ///
+        /// NOTE: messages are compressed with the default compression.
+        pub async fn send_transaction(
+            &self,
+            messages: Vec<Message<'_>>,
+            timeout: Duration,
+        ) -> Option<TransactionOutcome> {
+            let pool = self.transactional.as_ref()?;
+            let mut pooled = pool.acquire().await?;
+            let producer = pooled.producer.as_mut()?;
+
+            let outcome = self
+                .run_transaction(producer, messages, timeout, pool.timeout)
+                .await;
+            let result = if outcome.committed() {
+                "committed"
+            } else {
+                "aborted"
+            };
+            self.transactions_counter.with_label_values(&[result]).inc();
+            Some(outcome)
+        }
+
+        async fn run_transaction(
+            &self,
+            producer: &mut TransactionalProducer,
+            messages: Vec<Message<'_>>,
+            timeout: Duration,
+            transaction_timeout: Duration,
+        ) -> TransactionOutcome {
+            if let Err(err) = producer.begin(transaction_timeout).await {
+                // NOTE: messages were not sent, so every one of them fails
+                // with the transaction error.
+                let mut results = Vec::with_capacity(messages.len());
+                for message in messages.iter() {
+                    let failed = Producer::failed_message(message.topic, message.partition);
+                    results.push(Err((err.clone(), failed)));
+                }
+                if producer.initialized {
+                    producer.abort(transaction_timeout).await;
+                }
+                return TransactionOutcome {
+                    results,
+                    error: Some(err),
+                    mirror_error: None,
+                };
+            }
+
+            let results = futures::future::join_all(
+                messages
+                    .into_iter()
+                    .map(|message| self.send_with(&producer.producer, message, timeout)),
+            )
+            .await;
+
+            let mut error = None;
+            if results.iter().all(|r| r.is_ok()) {
+                error = producer.commit(transaction_timeout).await.err();
+                if error.is_none() {
+                    return TransactionOutcome {
+                        results,
+                        error,
+                        mirror_error: None,
+                    };
+                }
+            }
+            producer.abort(transaction_timeout).await;
+            TransactionOutcome {
+                results,
+                error,
+                mirror_error: None,
+            }
+        }
+
+        fn failed_message(topic: &str, partition: Option<i32>) -> OwnedMessage {
+            OwnedMessage::new(
+                None,
+                None,
+                topic.to_string(),
+                Timestamp::NotAvailable,
+                partition.unwrap_or(-1),
+                -1,
+                None,
+            )
+        }
+
+        async fn send_with(
             &self,
-            topic: &String,
-            data: &String,
-            key: Option<&String>,
-            partition: Option<i32>,
+            producer: &ThreadedProducer<KprfClientContext>,
+            message: Message<'_>,
             timeout: Duration,
         ) -> OwnedDeliveryResult {
+            let topic = message.topic;
+            let partition = message.partition;
             self.queue_size_gauge.with_label_values(&[&topic]).inc();
             self.sent_messages_counter
                 .with_label_values(&[&topic])
                 .inc();
-            let record = FutureRecord {
+            let (tx, rx) = tokio::sync::oneshot::channel();
+            let mut record = BaseRecord {
                 topic,
                 partition,
-                payload: Some(data),
-                key,
-                timestamp: None,
-                headers: None,
+                payload: message.payload,
+                key: message.key,
+                timestamp: message.timestamp,
+                headers: message.headers,
+                delivery_opaque: Box::new(tx),
             };
 
             let start = SystemTime::now();
-            let result = self.producer.send(record, timeout).await;
+            let delivery = async {
+                loop {
+                    match producer.send(record) {
+                        Ok(()) => {
+                            break rx.await.unwrap_or(Err((
+                                KafkaError::Canceled,
+                                Producer::failed_message(topic, partition),
+                            )))
+                        }
+                        // NOTE: message is enqueued again once the queue has
+                        // room for it, until timeout expires.
+                        Err((KafkaError::MessageProduction(RDKafkaErrorCode::QueueFull), r)) => {
+                            record = r;
+                            tokio::time::sleep(QUEUE_FULL_RETRY_INTERVAL).await;
+                        }
+                        Err((err, _)) => {
+                            break Err((err, Producer::failed_message(topic, partition)))
+                        }
+                    }
+                }
+            };
+            let result = tokio::time::timeout(timeout, delivery)
+                .await
+                .unwrap_or_else(|_| {
+                    Err((
+                        KafkaError::MessageProduction(RDKafkaErrorCode::MessageTimedOut),
+                        Producer::failed_message(topic, partition),
+                    ))
+                });
             self.message_send_duration
                 .with_label_values(&[&topic])
                 .observe(
@@ -588,43 +1692,103 @@ pub mod producer {
         }
     }
 
-    pub fn new(cfg: super::config::KafkaConfig) -> Arc<Producer> {
-        let cf = cfg.to_hash();
-        let mut client_config = rdkafka::ClientConfig::new();
-        for (k, v) in cf.iter() {
-            client_config.set(k, v);
+    /// Creates producer of the cluster. Its metrics are labelled with the
+    /// cluster name.
+    pub fn new(cluster: &str, cfg: super::config::KafkaConfig) -> Arc<Producer> {
+        let client_config = cfg.client_config();
+
+        let default_compression = cfg
+            .default_compression()
+            .map(|c| c.name())
+            .unwrap_or_else(|| String::from("none"));
+        let client_context = KprfClientContext::new(cluster, default_compression.clone());
+        let instances = client_context.instances.clone();
+        let brokers_up = client_context.brokers_up.clone();
+
+        // NOTE: librdkafka does not allow to configure compression per topic,
+        // so a separate producer instance is created for every compression
+        // used in overrides. Topics with the default compression use the
+        // default producer, so that instances are named by distinct
+        // compressions.
+        let mut compression_producers = HashMap::new();
+        let mut topic_producers = HashMap::new();
+        for (topic, compression) in cfg.topic_compression.iter() {
+            if compression.name() == default_compression {
+                continue;
+            }
+            let producer = compression_producers
+                .entry(compression.name())
+                .or_insert_with(|| {
+                    let mut client_config = client_config.clone();
+                    for (key, value) in compression.properties() {
+                        client_config.set(key, &value);
+                    }
+                    let context = client_context.with_compression(compression.name());
+                    new_kafka_producer(&client_config, context).unwrap_or_else(|err| {
+                        panic!("Failed to create threaded producer: {}", err.to_string())
+                    })
+                });
+            topic_producers.insert(topic.clone(), producer.clone());
         }
 
-        let client_context = KprfClientContext::new();
+        let transactional = if cfg.transactions.enabled {
+            let producers = (0..cfg.transactions.pool_size())
+                .map(|n| {
+                    let context = client_context.with_transactional_id(cfg.transactional_id(n));
+                    TransactionalProducer::new(cfg.transactional_client_config(n), context)
+                })
+                .collect::<Vec<_>>();
+            Some(TransactionalPool {
+                available: Arc::new(tokio::sync::Semaphore::new(producers.len())),
+                producers: Arc::new(tokio::sync::Mutex::new(producers)),
+                timeout: Duration::from_millis(cfg.transactions.timeout_ms() as u64),
+            })
+        } else {
+            None
+        };
 
-        let result = FutureProducer::from_config_and_context(&client_config, client_context);
+        let result = new_kafka_producer(&client_config, client_context);
         match result {
             Err(err) => panic!("Failed to create threaded producer: {}", err.to_string()),
             Ok(producer) => Arc::new(Producer {
                 producer,
+                topic_producers,
+                transactional,
+                idempotent: cfg.idempotent,
+                instances,
+                brokers_up,
                 queue_size_gauge: prometheus::register_int_gauge_vec!(
-                    "kafka_internal_queue_size",
-                    "Kafka internal queue size",
+                    prometheus::opts!("kafka_internal_queue_size", "Kafka internal queue size")
+                        .const_label("cluster", cluster),
                     &["topic"]
                 )
                 .unwrap(),
                 error_counter: prometheus::register_int_counter_vec!(
-                    "kafka_errors_count",
-                    "Kafka internal errors count",
+                    prometheus::opts!("kafka_errors_count", "Kafka internal errors count")
+                        .const_label("cluster", cluster),
                     &["topic", "error_code"]
                 )
                 .unwrap(),
                 sent_messages_counter: prometheus::register_int_counter_vec!(
-                    "kafka_sent_messages",
-                    "Kafka sent messages count",
+                    prometheus::opts!("kafka_sent_messages", "Kafka sent messages count")
+                        .const_label("cluster", cluster),
                     &["topic"]
                 )
                 .unwrap(),
                 message_send_duration: prometheus::register_histogram_vec!(
-                    "kafka_message_send_duration",
-                    "Kafka message send duration",
-                    &["topic"],
-                    prometheus::exponential_buckets(5.0, 2.0, 5).unwrap()
+                    prometheus::histogram_opts!(
+                        "kafka_message_send_duration",
+                        "Kafka message send duration",
+                        prometheus::exponential_buckets(5.0, 2.0, 5).unwrap()
+                    )
+                    .const_label("cluster", cluster),
+                    &["topic"]
+                )
+                .unwrap(),
+                transactions_counter: prometheus::register_int_counter_vec!(
+                    prometheus::opts!("kafka_transactions_count", "Kafka transactions count")
+                        .const_label("cluster", cluster),
+                    &["result"]
                 )
                 .unwrap(),
             }),
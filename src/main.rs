@@ -1,9 +1,14 @@
 mod config;
+mod grpc;
 mod http;
 mod kafka;
 mod log;
 mod metrics;
 
+use crate::http::api_handler::api::ApiHandler;
+use crate::http::api_handler::topics::{TopicPolicy, TopicRoutes};
+use crate::http::auth::{Authenticator, Authorizer};
+use crate::kafka::cluster::Clusters;
 use crate::log::kflog;
 use clap::ArgMatches;
 use std::sync::Arc;
@@ -46,27 +51,87 @@ async fn main() {
     let (shutdown_tx, shutdown_rx) = oneshot::channel::<String>();
     let (shutdown_metrics_tx, shutdown_metrics_rx) = oneshot::channel::<String>();
 
-    let kafka_producer = kafka::kafka::producer::new(cfg.get_kafka_config());
+    let clusters = Arc::new(
+        Clusters::new(
+            &logger,
+            cfg.get_kafka_config(),
+            cfg.get_clusters_config(),
+            &cfg.get_mirrors_config(),
+            &cfg.get_failovers_config(),
+        )
+        .unwrap_or_else(|e| panic!("invalid kafka config: {}", e)),
+    );
+    clusters.start_failovers(logger.clone());
+    let routes = Arc::new(
+        TopicRoutes::new(&cfg.get_routes_config(), &clusters.names())
+            .unwrap_or_else(|e| panic!("failed to initialize topic routes: {}", e)),
+    );
 
     let metrics_server = metrics::metrics::Server::new(metrics::metrics::ServerConfig {
-        port: http_config.metrics_port(),
+        addrs: http_config
+            .metrics_listen_addrs()
+            .unwrap_or_else(|e| panic!("failed to initialize metrics server: {}", e)),
     });
-    let metrics_shutdown_rx =
-        metrics_server.start_server(logger.clone(), shutdown_metrics_rx, app_info.clone());
+    let metrics_shutdown_rx = metrics_server.start_server(
+        logger.clone(),
+        shutdown_metrics_rx,
+        app_info.clone(),
+        clusters.clone(),
+    );
+
+    let auth_config = cfg.get_auth_config();
+    let authenticator = Arc::new(
+        Authenticator::new(&auth_config)
+            .unwrap_or_else(|e| panic!("failed to initialize authentication: {}", e)),
+    );
+    let authorizer = Arc::new(
+        Authorizer::new(&auth_config)
+            .unwrap_or_else(|e| panic!("failed to initialize topic acls: {}", e)),
+    );
+
+    let topic_policy = Arc::new(
+        TopicPolicy::new(&cfg.get_topics_config())
+            .unwrap_or_else(|e| panic!("failed to initialize topics policy: {}", e)),
+    );
+
+    let api_handler = ApiHandler::new(
+        logger.clone(),
+        clusters,
+        routes,
+        Arc::new(ratelimiter),
+        authorizer,
+        topic_policy,
+        cfg.get_api_config(),
+    );
 
     // TODO(shmel1k): improve graceful shutdown behavior.
     let main_server_shutdown_rx = http_server.start_server(
         logger.clone(),
-        kafka_producer.clone(),
-        Arc::new(ratelimiter),
+        api_handler.clone(),
+        authenticator.clone(),
         shutdown_rx,
     );
 
+    let grpc_config = cfg.get_grpc_config();
+    let grpc_server = if grpc_config.enabled() {
+        let (shutdown_grpc_tx, shutdown_grpc_rx) = oneshot::channel::<String>();
+        let mut grpc_server = init_grpc_server(grpc_config, http_config.tls());
+        let grpc_shutdown_rx =
+            grpc_server.start_server(logger.clone(), api_handler, authenticator, shutdown_grpc_rx);
+        Some((shutdown_grpc_tx, grpc_shutdown_rx))
+    } else {
+        None
+    };
+
     tokio::select! {
         _ = tokio::signal::ctrl_c() => {
             slog::info!(logger, "shutting down application");
             shutdown_tx.send(String::from("shutdown")).expect("failed to shutdown kafka-http server");
             shutdown_metrics_tx.send(String::from("shutdown")).expect("failed to shutdown metrics-http server");
+            if let Some((shutdown_grpc_tx, grpc_shutdown_rx)) = grpc_server {
+                shutdown_grpc_tx.send(String::from("shutdown")).expect("failed to shutdown grpc server");
+                grpc_shutdown_rx.await.ok();
+            }
             metrics_shutdown_rx.await.ok();
             main_server_shutdown_rx.await.ok();
         }
@@ -74,6 +139,25 @@ async fn main() {
 }
 
 fn init_http_server(http_config: config::HttpConfig) -> http::server::Server {
-    let http_server_config = http::server::Config::new(http_config.port());
+    let addrs = if http_config.tcp_enabled() {
+        http_config
+            .listen_addrs()
+            .unwrap_or_else(|e| panic!("failed to initialize http server: {}", e))
+    } else {
+        vec![]
+    };
+    let http_server_config =
+        http::server::Config::new(addrs, http_config.unix_socket(), http_config.tls());
     http::server::Server::new_from_config(http_server_config)
 }
+
+fn init_grpc_server(
+    grpc_config: config::GrpcConfig,
+    tls: Option<config::TlsConfig>,
+) -> grpc::server::Server {
+    let addrs = grpc_config
+        .listen_addrs()
+        .unwrap_or_else(|e| panic!("failed to initialize grpc server: {}", e));
+    let grpc_server_config = grpc::server::Config::new(addrs, tls);
+    grpc::server::Server::new_from_config(grpc_server_config)
+}
@@ -1,7 +1,14 @@
 use crate::config::AppMetadata;
+use crate::kafka::cluster::{Clusters, DEFAULT_CLUSTER};
+use crate::kafka::kafka::producer;
 use crate::log::kflog;
 use crate::log::kflog::Logger;
 use crate::metrics::metrics;
+use futures::FutureExt;
+use serde::Serialize;
+use std::collections::BTreeMap;
+use std::net::SocketAddr;
+use std::sync::Arc;
 use tokio::sync::oneshot;
 use tokio::sync::oneshot::Receiver;
 use warp::{Filter, Rejection, Reply};
@@ -41,8 +48,34 @@ pub(crate) async fn handler(logger: Logger) -> Result<impl Reply, Rejection> {
     Ok(result)
 }
 
+/// StatusResponse describes the application and its kafka producers.
+#[derive(Serialize)]
+struct StatusResponse {
+    version: String,
+    commit: String,
+    /// Producer of the default cluster.
+    kafka: Option<producer::Status>,
+    /// Producers of additional clusters.
+    #[serde(skip_serializing_if = "BTreeMap::is_empty")]
+    clusters: BTreeMap<String, producer::Status>,
+}
+
+pub(crate) async fn status_handler(
+    app_info: AppMetadata,
+    clusters: Arc<Clusters>,
+) -> Result<impl Reply, Rejection> {
+    let mut clusters = clusters.status();
+    let response = StatusResponse {
+        version: app_info.get_version(),
+        commit: app_info.get_commit_hash(),
+        kafka: clusters.remove(DEFAULT_CLUSTER),
+        clusters,
+    };
+    Ok(warp::reply::json(&response))
+}
+
 pub struct ServerConfig {
-    pub port: u16,
+    pub addrs: Vec<SocketAddr>,
 }
 
 pub struct Server {
@@ -73,38 +106,58 @@ impl Server {
         logger: kflog::Logger,
         shutdown_rx: Receiver<String>,
         app_info: AppMetadata,
+        clusters: Arc<Clusters>,
     ) -> Receiver<i8> {
-        self.initialize_base_metrics(app_info);
+        self.initialize_base_metrics(app_info.clone());
 
-        let route = warp::path!("metrics")
+        let metrics_route = warp::path!("metrics")
             .and(metrics::with_logger(logger.clone()))
             .and_then(metrics::handler);
+        let status_route = warp::path!("status")
+            .and(warp::get())
+            .and(warp::any().map(move || app_info.clone()))
+            .and(warp::any().map(move || clusters.clone()))
+            .and_then(metrics::status_handler);
+        let route = metrics_route.or(status_route);
 
         let (shutdown_completed_tx, shutdown_completed_rx) = oneshot::channel::<i8>();
 
-        slog::info!(
-            logger,
-            "starting metrics server";
-            "port" => self.config.port
-        );
-        let (_, server) =
-            warp::serve(route).bind_with_graceful_shutdown(([0, 0, 0, 0], self.config.port), {
-                async move {
-                    shutdown_rx.await.ok();
-                    slog::info!(logger, "shutting down metrics server");
-                    let send_result = shutdown_completed_tx.send(0);
-                    if send_result.is_err() {
-                        slog::error!(
-                            logger,
-                            "failed to send data to main_server shutdown channel: {}",
-                            send_result.err().unwrap()
-                        );
-                    }
-                }
-            });
-
-        let result = tokio::task::spawn(server);
-        tokio::task::spawn(async move { result.await });
+        let logger_cloned = logger.clone();
+        let shutdown = async move {
+            shutdown_rx.await.ok();
+            slog::info!(logger_cloned, "shutting down metrics server");
+        }
+        .shared();
+
+        let mut servers = vec![];
+        for addr in &self.config.addrs {
+            slog::info!(
+                logger,
+                "starting metrics server";
+                "addr" => addr.to_string(),
+            );
+            let (_, server) = match warp::serve(route.clone())
+                .try_bind_with_graceful_shutdown(*addr, shutdown.clone())
+            {
+                Ok(server) => server,
+                Err(e) => panic!("failed to bind metrics server to '{}': {}", addr, e),
+            };
+            servers.push(tokio::task::spawn(server));
+        }
+
+        tokio::task::spawn(async move {
+            for server in servers {
+                server.await.ok();
+            }
+            let send_result = shutdown_completed_tx.send(0);
+            if send_result.is_err() {
+                slog::error!(
+                    logger,
+                    "failed to send data to main_server shutdown channel: {}",
+                    send_result.err().unwrap()
+                );
+            }
+        });
         return shutdown_completed_rx;
     }
 }